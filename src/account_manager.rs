@@ -1,10 +1,36 @@
-use crate::user::User;
-use anyhow::{anyhow, bail, Context};
-use chrono::{FixedOffset, NaiveDateTime, Utc};
-use reqwest::{tls::Version, Client, ClientBuilder, Response};
-use scraper::{ElementRef, Html, Selector};
-use std::{collections::HashMap, net::IpAddr};
+use crate::{
+    connection_table::{self, LayoutError},
+    http_config::{HttpConfig, IpFamily},
+    http_trace::{EntryId, HttpTrace},
+    metrics::{Metrics, Operation},
+    tls::{self, HandshakeFailure, TlsError, TlsReport},
+    user::User,
+};
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    header::DATE,
+    redirect::{Attempt, Policy},
+    Client, ClientBuilder, RequestBuilder, Response, Url,
+};
+use scraper::{Html, Selector};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    future::Future,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use tracing::Instrument;
 
+const HOST: &str = "netaccess.iitm.ac.in";
 const URL: &str = "https://netaccess.iitm.ac.in";
 const LOGIN_PATH: &str = "/account/login";
 const INDEX_PATH: &str = "/account/index";
@@ -14,310 +40,1395 @@ const REVOKE_PATH: &str = "/account/revoke";
 const USER_NAME_FIELD: &str = "userLogin";
 const PASSWORD_FIELD: &str = "userPassword";
 
+/// Number of redirects followed before a request fails, as with the default policy of reqwest.
+const MAX_REDIRECTS: usize = 10;
+
+/// Clock skew beyond which the system clock is considered wrong, in seconds.
+const CLOCK_SKEW_THRESHOLD: i64 = 60;
+
 const DURATION_FIELD: &str = "duration";
+/// Duration names accepted by earlier versions, with their length in minutes.
+const DURATION_ALIASES: [(&str, u64); 3] =
+    [("hour", 60), ("day", 24 * 60), ("month", 30 * 24 * 60)];
 const APPROVE_BTN_FIELD: &str = "approveBtn";
 
 lazy_static::lazy_static! {
     static ref INDIA_TZ: FixedOffset =
         FixedOffset::east_opt(5 * 3600 + 30 * 60).expect("Failed to create India timezone");
 
-    static ref TBODY_SELECTOR: Selector =
-        Selector::parse("tbody").expect("Failed to create tbody selector");
-    static ref TR_SELECTOR: Selector =
-        Selector::parse("tr").expect("Failed to create tr selector");
-    static ref TD_SELECTOR: Selector =
-        Selector::parse("td").expect("Failed to create td selector");
-    static ref SPAN_SELECTOR: Selector =
-        Selector::parse("span").expect("Failed to create span selector");
+    static ref ALERT_SELECTOR: Selector =
+        Selector::parse(".alert").expect("Failed to create alert selector");
+    static ref DURATION_OPTION_SELECTOR: Selector =
+        Selector::parse(&format!("select[name={DURATION_FIELD}] option"))
+            .expect("Failed to create duration option selector");
+}
+
+tokio::task_local! {
+    /// URLs of the redirect chain followed by the request being sent, starting at the request
+    /// URL. Scoped to each request, as requests may be sent concurrently.
+    static REDIRECTS: RefCell<Vec<Url>>;
 }
 
+/// An IP address authorization registered with the portal.
 #[derive(Debug, Clone, Copy)]
 pub struct Connection {
+    /// Time left until the authorization expires, zero if it already has.
     pub time_left: chrono::Duration,
-    is_active: bool,
+    /// Time in the portal's timezone until which the authorization is valid, if registered.
+    pub valid_till: Option<DateTime<FixedOffset>>,
+    pub(crate) is_active: bool,
 }
 
 impl Default for Connection {
     fn default() -> Self {
         Self {
             time_left: chrono::Duration::zero(),
+            valid_till: None,
             is_active: false,
         }
     }
 }
 
 impl Connection {
+    /// Creates a connection as listed by the portal, such as for implementors of
+    /// [`PortalClient`](crate::PortalClient).
+    pub fn new(
+        time_left: chrono::Duration,
+        valid_till: Option<DateTime<FixedOffset>>,
+        is_active: bool,
+    ) -> Self {
+        Self {
+            time_left,
+            valid_till,
+            is_active,
+        }
+    }
+
+    /// Whether the portal marks the connection as active and it has not expired yet.
     pub fn is_active(&self) -> bool {
         !self.time_left.is_zero() && self.is_active
     }
 }
 
+/// Status of the IP address of the system this application is running on.
 #[derive(Debug, Clone, Copy)]
 pub struct SystemStatus {
+    /// Local IP address of the system.
     pub ip: IpAddr,
+    /// Connection of the system IP address, defaulting to an inactive one if not registered.
     pub connection: Connection,
+    /// Offset of the portal clock from the system clock, estimated from the `Date` header of
+    /// portal responses. Time left is already corrected for it.
+    pub clock_skew: chrono::Duration,
+}
+
+impl SystemStatus {
+    /// Whether the system clock deviates from the portal clock by more than a minute, which
+    /// would make it display wrong expiry times.
+    pub fn is_clock_skewed(&self) -> bool {
+        self.clock_skew.num_seconds().abs() > CLOCK_SKEW_THRESHOLD
+    }
 }
 
+/// Status of a user account as displayed on the portal index page.
 #[derive(Debug, Clone)]
 pub struct Status {
+    /// Status of the system IP address.
     pub system_status: SystemStatus,
-    connections: HashMap<IpAddr, Connection>,
+    pub(crate) system_registered: bool,
+    pub(crate) connections: HashMap<IpAddr, Connection>,
+    pub(crate) alerts: Vec<String>,
+    pub(crate) durations: Vec<DurationOption>,
 }
 
 impl Status {
+    /// All connections registered for the account, including the system IP address.
+    fn table(&self) -> HashMap<IpAddr, Connection> {
+        let mut table = self.connections.clone();
+        if self.system_registered {
+            table.insert(self.system_status.ip, self.system_status.connection);
+        }
+        table
+    }
+
+    /// Connections registered for the account other than the system IP address.
     pub fn connections(&self) -> &HashMap<IpAddr, Connection> {
         &self.connections
     }
 
-    fn is_connection_active(&self, ip: &IpAddr) -> bool {
-        self.connections.get(ip).is_some_and(Connection::is_active)
+    /// Alert messages displayed by the portal on the index page.
+    pub fn alerts(&self) -> &[String] {
+        &self.alerts
+    }
+
+    /// Approval durations offered by the portal form.
+    pub fn durations(&self) -> &[DurationOption] {
+        &self.durations
+    }
+
+    /// Finds the offered duration matching the requested label or form value, ignoring case and
+    /// whitespace, or the default duration of the form if none is requested. The names `hour`,
+    /// `day` and `month` of earlier versions match the offered duration of that length.
+    pub fn duration(&self, requested: Option<&str>) -> Result<&DurationOption, Error> {
+        let Some(requested) = requested else {
+            return self
+                .durations
+                .iter()
+                .find(|duration| duration.is_default)
+                .or_else(|| self.durations.first())
+                .ok_or(Error::Parse(LayoutError::MissingDurations));
+        };
+        let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let requested = normalize(requested);
+        let alias_minutes = DURATION_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(&requested))
+            .map(|(_, minutes)| *minutes);
+        self.durations
+            .iter()
+            .find(|duration| {
+                normalize(&duration.label).eq_ignore_ascii_case(&requested)
+                    || duration.value == requested
+            })
+            .or_else(|| {
+                self.durations.iter().find(|duration| {
+                    alias_minutes.is_some() && label_minutes(&duration.label) == alias_minutes
+                })
+            })
+            .ok_or_else(|| Error::UnknownDuration {
+                requested,
+                available: self
+                    .durations
+                    .iter()
+                    .map(|duration| duration.label.clone())
+                    .collect(),
+            })
+    }
+
+    fn connection(&self, ip: &IpAddr) -> Option<&Connection> {
+        if *ip == self.system_status.ip {
+            self.system_registered
+                .then_some(&self.system_status.connection)
+        } else {
+            self.connections.get(ip)
+        }
+    }
+}
+
+/// Length in minutes of a duration label such as `60 minutes` or `1 month`, counting a month as
+/// 30 days.
+fn label_minutes(label: &str) -> Option<u64> {
+    let (count, unit) = label.trim().split_once(char::is_whitespace)?;
+    let unit_minutes = match unit.trim().to_ascii_lowercase().trim_end_matches('s') {
+        "minute" => 1,
+        "hour" => 60,
+        "day" => 24 * 60,
+        "week" => 7 * 24 * 60,
+        "month" => 30 * 24 * 60,
+        _ => return None,
+    };
+    count.parse::<u64>().ok()?.checked_mul(unit_minutes)
+}
+
+/// An approval duration offered by the portal form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurationOption {
+    /// Value submitted with the form.
+    pub value: String,
+    /// Label displayed by the portal, such as `1 day`.
+    pub label: String,
+    /// Whether the form selects this duration by default.
+    pub is_default: bool,
+}
+
+/// Outcome of an approve or revoke operation, distinguishing no-op cases from actual changes.
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    /// The IP address was approved and is now active.
+    Approved {
+        /// The connection as registered by the portal after approval.
+        connection: Connection,
+    },
+    /// The IP address is already active, so approval was skipped.
+    AlreadyActive {
+        /// Time left until the existing approval expires.
+        time_left: chrono::Duration,
+    },
+    /// The IP address was revoked and is no longer active.
+    Revoked,
+    /// The IP address is not registered with the portal, so there was nothing to revoke.
+    NotRegistered,
+    /// The IP address is already inactive, so there was nothing to revoke.
+    AlreadyInactive,
+}
+
+/// Result of an approve or revoke operation on an IP address, along with any alert messages
+/// the portal displayed in response.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The IP address that was approved or revoked.
+    pub ip: IpAddr,
+    /// What the operation did.
+    pub outcome: Outcome,
+    /// Alert messages displayed by the portal.
+    pub alerts: Vec<String>,
+}
+
+fn fmt_alerts(alerts: &[String]) -> String {
+    if alerts.is_empty() {
+        String::new()
+    } else {
+        format!(" (portal says: {})", alerts.join("; "))
     }
 }
 
+/// Errors encountered while interacting with the portal.
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
+    /// The HTTP request failed, see [`Error::is_unreachable`] for connectivity failures.
     #[error("HTTP request error encountered during an operation: {0}")]
     Reqwest(#[from] reqwest::Error),
-    #[error("Invalid user credentials")]
-    InvalidCredentials,
+    /// The portal rejected the credentials, along with the alerts it displayed.
+    #[error("Invalid user credentials{}", fmt_alerts(.0))]
+    InvalidCredentials(Vec<String>),
+    /// The portal responded with an unexpected status or redirect.
+    #[error("Unexpected response from portal: {0}")]
+    UnexpectedResponse(String),
+    /// A portal page could not be parsed.
+    #[error("Portal layout changed, {0}")]
+    Parse(#[from] LayoutError),
+    /// The local IP address of the system could not be determined.
+    #[error("Failed to get local ip address: {0}")]
+    LocalIp(#[from] local_ip_address::Error),
+    /// A user provided IP address could not be parsed.
+    #[error("Ip address is malformed {0}")]
+    MalformedIp(String),
+    /// The requested approval duration is not offered by the portal.
+    #[error(
+        "Duration {requested} is not offered by the portal, available durations are {available:?}"
+    )]
+    UnknownDuration {
+        /// The requested duration.
+        requested: String,
+        /// Labels of the durations offered by the portal.
+        available: Vec<String>,
+    },
+    /// The portal accepted a request but its connection table does not reflect it.
+    #[error("Portal did not apply the {action} request for {ip}{}", fmt_alerts(.alerts))]
+    NotApplied {
+        /// The request that was not applied, either approve or revoke.
+        action: &'static str,
+        /// The IP address the request was made for.
+        ip: IpAddr,
+        /// Alert messages displayed by the portal.
+        alerts: Vec<String>,
+    },
+    /// TLS could not be configured for the portal, or the handshake of a TLS check failed.
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+    /// Any other error.
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+impl Error {
+    /// Whether the error was caused by the portal being unreachable, either due to a failed
+    /// connection or a timeout.
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, Self::Reqwest(err) if err.is_connect() || err.is_timeout())
+            && !self.is_certificate_rejected()
+            && !self.is_tls_incompatible()
+    }
+
+    /// Whether the TLS handshake with the portal failed because it offers no protocol version,
+    /// cipher suite or key exchange that is supported.
+    pub fn is_tls_incompatible(&self) -> bool {
+        match self {
+            Self::Reqwest(err) => {
+                tls::handshake_failure(err) == Some(HandshakeFailure::Incompatible)
+            }
+            Self::Tls(err) => err.is_incompatible(),
+            _ => false,
+        }
+    }
+
+    /// Whether the certificate of the portal failed verification, either against the trusted
+    /// roots or the pins of the [`TlsConfig`](crate::TlsConfig).
+    pub fn is_certificate_rejected(&self) -> bool {
+        matches!(self, Self::Reqwest(err) if rejects_certificate(err))
+    }
+
+    /// Short name of the kind of error in snake case, such as `unreachable`.
+    pub fn category(&self) -> &'static str {
+        match self {
+            err if err.is_certificate_rejected() => "certificate",
+            err if err.is_tls_incompatible() => "tls_incompatible",
+            err if err.is_unreachable() => "unreachable",
+            Self::Reqwest(_) => "http",
+            Self::InvalidCredentials(_) => "credentials",
+            Self::UnexpectedResponse(_) => "unexpected_response",
+            Self::Parse(_) => "layout",
+            Self::LocalIp(_) => "local_ip",
+            Self::MalformedIp(_) | Self::UnknownDuration { .. } => "invalid_input",
+            Self::NotApplied { .. } => "not_applied",
+            Self::Tls(_) => "tls",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+fn rejects_certificate(err: &reqwest::Error) -> bool {
+    tls::handshake_failure(err) == Some(HandshakeFailure::CertificateRejected)
+}
+
+/// Whether a request failed in a way that may well not happen again, such as a timeout, a
+/// failed connection or a server error. Rejected certificates and handshakes failing as
+/// incompatible are never worth another try.
+fn is_transient(result: &reqwest::Result<Response>) -> bool {
+    match result {
+        Ok(response) => response.status().is_server_error(),
+        Err(err) => (err.is_timeout() || err.is_connect()) && tls::handshake_failure(err).is_none(),
+    }
+}
+
+/// Delay before the given retry, counting from one, doubling the base delay every time.
+fn backoff(base: std::time::Duration, retry: u32) -> std::time::Duration {
+    base.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+}
+
+/// Outcome of posting a request that changes the state of the portal.
+enum Posted {
+    /// The portal responded to the request.
+    Response(Response),
+    /// The response got lost, but the status shows the portal applied the request.
+    Applied(Status),
+}
+
+/// Client for the netaccess portal, managing the session of a user account along with the
+/// authorization of IP addresses.
 #[derive(Debug)]
 pub struct AccountManager {
     client: Client,
+    /// Scheme and host of the portal, which tests point at a local server.
+    base_url: String,
+    cookies: Arc<Jar>,
+    config: HttpConfig,
+    dump_dir: Option<PathBuf>,
+    dump_count: AtomicUsize,
+    http_trace: Option<HttpTrace>,
+    clock_skew: Mutex<chrono::Duration>,
+    metrics: Metrics,
 }
 
 impl AccountManager {
-    pub fn new() -> reqwest::Result<Self> {
-        ClientBuilder::default()
-            .min_tls_version(Version::TLS_1_2)
-            .cookie_store(true)
-            .timeout(std::time::Duration::from_secs(5))
-            .build()
-            .map(|client| Self { client })
+    /// Creates a client with a fresh session, trusting the root certificates of the system.
+    pub fn new() -> Result<Self, Error> {
+        Self::with_config(HttpConfig::default())
+    }
+
+    /// Creates a client with a fresh session, connecting to the portal and verifying its
+    /// certificate as configured.
+    pub fn with_config(config: HttpConfig) -> Result<Self, Error> {
+        let cookies = Arc::new(Jar::default());
+        let redirect_policy = Policy::custom(|attempt: Attempt| {
+            // The policy is checked while the request is polled, so within its scope
+            let _ = REDIRECTS.try_with(|redirects| {
+                *redirects.borrow_mut() = attempt
+                    .previous()
+                    .iter()
+                    .chain([attempt.url()])
+                    .cloned()
+                    .collect()
+            });
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = config
+            .tls
+            .configure(config.apply(ClientBuilder::default())?)?
+            .cookie_provider(Arc::clone(&cookies))
+            .redirect(redirect_policy)
+            .build()?;
+        Ok(Self {
+            client,
+            base_url: String::from(URL),
+            cookies,
+            config,
+            dump_dir: None,
+            dump_count: AtomicUsize::default(),
+            http_trace: None,
+            clock_skew: Mutex::new(chrono::Duration::zero()),
+            metrics: Metrics::default(),
+        })
     }
 
-    pub async fn check_user_passowrd(&self, user: &User) -> Result<(), Error> {
-        self.login(user, true).await
+    /// Writes every portal page that gets parsed to the given directory, so that they can be
+    /// contributed as test fixtures when the portal changes.
+    pub fn with_dump_dir(mut self, dump_dir: PathBuf) -> Self {
+        self.dump_dir = Some(dump_dir);
+        self
     }
 
-    fn local_ip() -> anyhow::Result<IpAddr> {
-        local_ip_address::local_ip().context("Failed to get local ip address")
+    /// Records every request and response to the given trace, with credentials redacted.
+    pub fn with_http_trace(mut self, http_trace: HttpTrace) -> Self {
+        self.http_trace = Some(http_trace);
+        self
+    }
+
+    #[cfg(test)]
+    fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Counts, latencies and errors of the operations performed so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    async fn timed<T>(
+        &self,
+        operation: Operation,
+        future: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let start = Instant::now();
+        let result = future.await;
+        self.metrics
+            .record(operation, start.elapsed(), result.as_ref().err());
+        result
     }
 
+    /// Sends a request to the portal within a span recording its method, path, status, the path
+    /// it got redirected to and how long it took. Request bodies are never recorded, as the
+    /// login form carries the password. The full exchange is written to the HTTP trace if any.
+    async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let request = request.build()?;
+        let path = request.url().path().to_owned();
+        let traced = self.http_trace.as_ref().and_then(|_| {
+            let cookies = self.cookies.cookies(request.url());
+            request.try_clone().map(|request| (request, cookies))
+        });
+        let span = tracing::info_span!(
+            "http_request",
+            method = %request.method(),
+            path,
+            status = tracing::field::Empty,
+            redirected_to = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        );
+        let start = Instant::now();
+        let (mut result, redirects) = REDIRECTS
+            .scope(RefCell::default(), async {
+                let result = self.client.execute(request).instrument(span.clone()).await;
+                (result, REDIRECTS.with(RefCell::take))
+            })
+            .await;
+        let elapsed = start.elapsed();
+        span.record("elapsed_ms", elapsed.as_millis() as u64);
+        if let (Some(http_trace), Some((request, cookies))) = (&self.http_trace, traced) {
+            let entry = http_trace.record(&request, cookies, &redirects, &result, elapsed);
+            if let Ok(response) = &mut result {
+                response.extensions_mut().insert(entry);
+            }
+        }
+        let _entered = span.enter();
+        match &result {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+                if response.url().path() != path {
+                    span.record("redirected_to", response.url().path());
+                }
+                tracing::info!("Portal responded");
+            }
+            Err(err) => tracing::info!(error = %err, "Portal request failed"),
+        }
+        result
+    }
+
+    /// Sends a request that can be repeated without side effects, retrying transient failures
+    /// with backoff as long as the retry budget allows.
+    async fn send_idempotent(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        for retry in 1.. {
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            let result = self.send(attempt).await;
+            if retry > self.config.retries || !is_transient(&result) {
+                return result;
+            }
+            self.wait_before_retry(retry, &result).await;
+        }
+        self.send(request).await
+    }
+
+    /// Posts a request that changes the state of the portal. Posting it again is only safe if
+    /// the portal did not apply it already, so after a transient failure the status is queried
+    /// to find out whether it did before the response got lost.
+    async fn post(
+        &self,
+        user: &User,
+        request: RequestBuilder,
+        applied: impl Fn(&Status) -> bool,
+    ) -> Result<Posted, Error> {
+        for retry in 1.. {
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            let result = self.send(attempt).await;
+            if retry > self.config.retries || !is_transient(&result) {
+                return Ok(Posted::Response(result?));
+            }
+            self.wait_before_retry(retry, &result).await;
+            let status = self.fetch_status(user).await?;
+            if applied(&status) {
+                return Ok(Posted::Applied(status));
+            }
+        }
+        Ok(Posted::Response(self.send(request).await?))
+    }
+
+    async fn wait_before_retry(&self, retry: u32, result: &reqwest::Result<Response>) {
+        let delay = backoff(self.config.retry_backoff, retry);
+        match result {
+            Ok(response) => tracing::warn!(
+                retry,
+                status = response.status().as_u16(),
+                delay_ms = delay.as_millis() as u64,
+                "Retrying portal request"
+            ),
+            Err(err) => tracing::warn!(
+                retry,
+                error = %err,
+                delay_ms = delay.as_millis() as u64,
+                "Retrying portal request"
+            ),
+        }
+        tokio::time::sleep(delay).await;
+    }
+
+    async fn page_text(&self, response: Response) -> Result<String, Error> {
+        self.record_clock_skew(&response);
+        let path = response.url().path().to_owned();
+        let entry = response.extensions().get::<EntryId>().copied();
+        let text = response.text().await?;
+        if let (Some(http_trace), Some(entry)) = (&self.http_trace, entry) {
+            http_trace.record_body(entry, &text);
+        }
+        if let Some(dump_dir) = &self.dump_dir {
+            let count = self.dump_count.fetch_add(1, Ordering::SeqCst) + 1;
+            let name = path.trim_matches('/').replace('/', "_");
+            let file = dump_dir.join(format!("{count:02}-{name}.html"));
+            fs::create_dir_all(dump_dir)
+                .and_then(|_| fs::write(&file, &text))
+                .with_context(|| format!("Failed to dump html to {}", file.display()))?;
+        }
+        Ok(text)
+    }
+
+    /// Performs a TLS handshake with the portal without logging in, reporting the negotiated
+    /// protocol and cipher suite along with the certificate chain and whether it passes
+    /// verification.
+    pub async fn check_tls(&self) -> Result<TlsReport, Error> {
+        Ok(tls::check(&self.config, HOST).await?)
+    }
+
+    /// Logs in with the credentials of the user, failing with [`Error::InvalidCredentials`] if
+    /// they are rejected.
+    #[tracing::instrument(skip_all, fields(user = user.name()))]
+    pub async fn check_user_password(&self, user: &User) -> Result<(), Error> {
+        self.timed(Operation::Login, self.login(user)).await
+    }
+
+    /// Addresses of the network interfaces of the system that the portal may see requests come
+    /// from, leaving out loopback and link local ones.
+    fn interface_ips() -> Vec<IpAddr> {
+        local_ip_address::list_afinet_netifas()
+            .map(|interfaces| {
+                interfaces
+                    .into_iter()
+                    .map(|(_, ip)| ip)
+                    .filter(|ip| match ip {
+                        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_link_local(),
+                        IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unicast_link_local(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The local address requests are sent from, which is the one the portal registers. Unless
+    /// configured, it is the address of a network interface that shows up in the connection
+    /// table, as requests may reach the portal over either family, or the address the system
+    /// prefers otherwise.
+    fn local_ip(&self, connections: &HashMap<IpAddr, Connection>) -> Result<IpAddr, Error> {
+        if let Some(local_address) = self.config.local_address {
+            return Ok(local_address);
+        }
+        let family = self.config.family;
+        let registered = Self::interface_ips().into_iter().find(|ip| {
+            family.is_none_or(|family| family.matches(ip)) && connections.contains_key(ip)
+        });
+        if let Some(ip) = registered {
+            return Ok(ip);
+        }
+        match family {
+            Some(IpFamily::V6) => local_ip_address::local_ipv6(),
+            Some(IpFamily::V4) | None => local_ip_address::local_ip(),
+        }
+        .map_err(Error::from)
+    }
+
+    /// Queries the status of the system IP address and all other connections of the user.
+    #[tracing::instrument(skip_all, fields(user = user.name()))]
     pub async fn status(&self, user: &User) -> Result<Status, Error> {
-        self.login(user, false).await?;
+        self.timed(Operation::Status, self.fetch_status(user)).await
+    }
+
+    async fn fetch_status(&self, user: &User) -> Result<Status, Error> {
+        // Sessions expire, so long running monitors log in again every now and then
+        if !self.is_logged_in().await? {
+            self.timed(Operation::Login, self.login(user)).await?;
+        }
         let index_page = self.index_page_response().await?;
-        let html = index_page.text().await?;
-        let mut connections = Self::parse_connections(&html)?;
-        let ip = Self::local_ip()?;
-        let system_connection = SystemStatus {
-            ip,
-            connection: connections.remove(&ip).unwrap_or_default(),
-        };
-        Ok(Status {
-            system_status: system_connection,
+        let html = Html::parse_document(&self.page_text(index_page).await?);
+        let mut connections = Self::parse_connections(&html, self.time_now())?;
+        let alerts = Self::parse_alerts(&html);
+        let durations = Self::parse_durations(&html);
+        let ip = self.local_ip(&connections)?;
+        let system_connection = connections.remove(&ip);
+        let status = Status {
+            system_status: SystemStatus {
+                ip,
+                connection: system_connection.unwrap_or_default(),
+                clock_skew: self.clock_skew(),
+            },
+            system_registered: system_connection.is_some(),
             connections,
-        })
+            alerts,
+            durations,
+        };
+        self.metrics.record_status(&status);
+        Ok(status)
     }
 
-    async fn login(&self, user: &User, force: bool) -> Result<(), Error> {
-        if !force && self.is_logged_in().await? {
-            return Ok(());
-        }
+    async fn login(&self, user: &User) -> Result<(), Error> {
         let login_form = HashMap::from([
             (USER_NAME_FIELD, user.name()),
             (PASSWORD_FIELD, user.password()),
         ]);
+        // Logging in again only renews the session, so it is as safe to retry as a query
         let response = self
-            .client
-            .post(format!("{URL}{LOGIN_PATH}"))
-            .form(&login_form)
-            .send()
+            .send_idempotent(
+                self.client
+                    .post(format!("{}{LOGIN_PATH}", self.base_url))
+                    .form(&login_form),
+            )
             .await?;
         if !response.status().is_success() {
-            return Err(Error::Other(anyhow!(
+            return Err(Error::UnexpectedResponse(format!(
                 "Login response failed with status {}",
                 response.status()
             )));
         }
         match response.url().path() {
             INDEX_PATH => Ok(()),
-            LOGIN_PATH => Err(Error::InvalidCredentials),
-            other => Err(Error::Other(anyhow!(
+            LOGIN_PATH => {
+                let html = Html::parse_document(&self.page_text(response).await?);
+                Err(Error::InvalidCredentials(Self::parse_alerts(&html)))
+            }
+            other => Err(Error::UnexpectedResponse(format!(
                 "Unexpected URL path in login response {other}"
             ))),
         }
     }
 
-    async fn is_logged_in(&self) -> anyhow::Result<bool> {
+    async fn is_logged_in(&self) -> Result<bool, Error> {
         let response = self.index_page_response().await?;
         if response.status().is_success() {
             Ok(response.url().path() == INDEX_PATH)
         } else {
-            Err(anyhow!(
+            Err(Error::UnexpectedResponse(format!(
                 "Index page response is not a success: {response:?}"
-            ))
+            )))
         }
     }
 
     async fn index_page_response(&self) -> reqwest::Result<Response> {
-        self.client.get(format!("{URL}{INDEX_PATH}")).send().await
-    }
-
-    fn time_now() -> NaiveDateTime {
-        Utc::now().with_timezone(&*INDIA_TZ).naive_local()
-    }
-
-    fn extract_text(element: ElementRef) -> Option<String> {
-        element
-            .children()
-            .next()
-            .and_then(|node| node.value().as_text())
-            .map(|text| text.to_string())
-    }
-
-    fn parse_tr_element(tr_element: ElementRef) -> anyhow::Result<(IpAddr, Connection)> {
-        /*
-        <tbody>
-            <tr>
-                <th>MAC</th>
-                <th align="center">IP</th>
-                <th>Valid till</th>
-                <th>Download today</th>
-                <th colspan="2">Status</th>
-            </tr>
-            <tr>
-                <td>
-                    <E:XP:IR:ED:>
-                </td>
-                <td>XX.XX.XX.XXX</td>
-                <td>24 Jul 2023, 10:07</td>
-                <td>     0 B</td>
-                <td><span class='label label-success'>Active</span></td>
-                <td><a href="/account/revoke/XX.XX.XX.XXX"><span class='label label-danger'>Delete</span></a></td>
-            </tr>
-        </tbody>
-         */
-
-        let td_elements = tr_element.select(&TD_SELECTOR);
-        // First td element is of no use either.
-        let mut td_elements = td_elements.skip(1);
-
-        let Some(ip_element) = td_elements.next() else {
-            bail!("Missing IP address element");
-        };
-        let ip_address = Self::extract_text(ip_element).context("Extracting IP address failed")?;
+        self.send_idempotent(self.client.get(format!("{}{INDEX_PATH}", self.base_url)))
+            .await
+            .inspect(|response| self.record_clock_skew(response))
+    }
 
-        let Some(valid_till_element) = td_elements.next() else {
-            bail!("Missing remaining duration element");
+    /// Estimates the offset of the portal clock from the system clock using the `Date` header
+    /// of a response. The header has a resolution of a second, which is plenty for expiry
+    /// times displayed in minutes.
+    fn record_clock_skew(&self, response: &Response) {
+        let Some(date) = response
+            .headers()
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        else {
+            return;
         };
-        let valid_till = Self::extract_text(valid_till_element)
-            .context("Extracting remaining duration failed")?;
-        let valid_till = NaiveDateTime::parse_from_str(&valid_till, "%d %b %Y, %H:%M")?;
+        *self.clock_skew.lock().expect("Clock skew lock poisoned") =
+            date.with_timezone(&Utc) - Utc::now();
+    }
 
-        let Some(status_element) = tr_element.select(&SPAN_SELECTOR).next() else {
-            bail!("Missing status element");
-        };
-        let status = Self::extract_text(status_element).context("Extracting status failed")?;
-
-        Ok((
-            ip_address.parse::<IpAddr>()?,
-            Connection {
-                time_left: chrono::Duration::max(
-                    chrono::Duration::zero(),
-                    valid_till - Self::time_now(),
-                ),
-                is_active: &status == "Active",
-            },
-        ))
+    fn clock_skew(&self) -> chrono::Duration {
+        *self.clock_skew.lock().expect("Clock skew lock poisoned")
     }
 
-    fn parse_connections(html: &str) -> anyhow::Result<HashMap<IpAddr, Connection>> {
-        let html = Html::parse_document(html);
-        let Some(tbody) = html.select(&TBODY_SELECTOR).next() else {
-            bail!("Html does not have a tbody element")
-        };
-        tbody
-            .select(&TR_SELECTOR)
-            .skip(1)
-            .map(Self::parse_tr_element)
+    /// Current time in the portal's timezone, corrected for the skew of the system clock.
+    fn time_now(&self) -> DateTime<FixedOffset> {
+        (Utc::now() + self.clock_skew()).with_timezone(&*INDIA_TZ)
+    }
+
+    /// Collects the text of alert messages rendered on a portal page, such as a wrong password
+    /// or an exceeded quota, with whitespace normalized and the close button removed.
+    fn parse_alerts(html: &Html) -> Vec<String> {
+        html.select(&ALERT_SELECTOR)
+            .map(|alert| {
+                alert
+                    .text()
+                    .flat_map(str::split_whitespace)
+                    .filter(|word| *word != "×")
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|alert| !alert.is_empty())
+            .collect()
+    }
+
+    fn parse_durations(html: &Html) -> Vec<DurationOption> {
+        html.select(&DURATION_OPTION_SELECTOR)
+            .filter_map(|option| {
+                let label = option
+                    .text()
+                    .flat_map(str::split_whitespace)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let value = option
+                    .value()
+                    .attr("value")
+                    .map_or_else(|| label.clone(), |value| value.trim().to_owned());
+                (!value.is_empty()).then(|| DurationOption {
+                    value,
+                    label,
+                    is_default: option.value().attr("selected").is_some(),
+                })
+            })
             .collect()
     }
 
+    fn parse_connections(
+        html: &Html,
+        time_now: DateTime<FixedOffset>,
+    ) -> Result<HashMap<IpAddr, Connection>, LayoutError> {
+        Ok(connection_table::parse(html)?
+            .into_iter()
+            .map(|row| {
+                // Portal times are in India's fixed offset, so there are no ambiguous local times
+                let valid_till = INDIA_TZ.from_utc_datetime(&(row.valid_till - *INDIA_TZ));
+                (
+                    row.ip,
+                    Connection {
+                        time_left: chrono::Duration::max(
+                            chrono::Duration::zero(),
+                            valid_till - time_now,
+                        ),
+                        valid_till: Some(valid_till),
+                        is_active: row.is_active,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Approves the system IP address for a duration offered by the portal, matched by label or
+    /// form value, or the default duration if none is given. Approval is skipped if the IP
+    /// address is already active, unless forced.
+    #[tracing::instrument(skip(self, user), fields(user = user.name()))]
     pub async fn approve(
         &self,
         user: &User,
-        duration_index: usize,
+        duration: Option<&str>,
         force: bool,
-    ) -> Result<IpAddr, Error> {
-        let status = self.status(user).await?;
+    ) -> Result<Report, Error> {
+        self.timed(
+            Operation::Approve,
+            self.approve_duration(user, duration, force),
+        )
+        .await
+    }
 
-        let SystemStatus { ip, connection } = status.system_status;
+    async fn approve_duration(
+        &self,
+        user: &User,
+        duration: Option<&str>,
+        force: bool,
+    ) -> Result<Report, Error> {
+        let status = self.fetch_status(user).await?;
+        let duration = status.duration(duration)?.value.clone();
+        let before = status.table();
+
+        let SystemStatus { ip, connection, .. } = status.system_status;
 
         if !force && connection.is_active() {
-            return Ok(ip);
+            return Ok(Report {
+                ip,
+                outcome: Outcome::AlreadyActive {
+                    time_left: connection.time_left,
+                },
+                alerts: status.alerts,
+            });
         }
 
         let approve_form = HashMap::from([
-            (DURATION_FIELD, duration_index.to_string()),
+            (DURATION_FIELD, duration),
             (APPROVE_BTN_FIELD, String::new()),
         ]);
 
-        let response = self
-            .client
-            .post(format!("{URL}{APPROVE_PATH}"))
-            .form(&approve_form)
-            .send()
+        let posted = self
+            .post(
+                user,
+                self.client
+                    .post(format!("{}{APPROVE_PATH}", self.base_url))
+                    .form(&approve_form),
+                |status| {
+                    self.approved_connection(ip, &before, &status.table())
+                        .is_some()
+                },
+            )
             .await?;
+        let (after, alerts) = match posted {
+            Posted::Response(response) => self.redirected_connections(response, "approve").await?,
+            Posted::Applied(status) => (status.table(), status.alerts),
+        };
 
-        if !response.status().is_success() {
-            return Err(Error::Other(anyhow!(
-                "Approve response failed with status {}",
-                response.status()
-            )));
+        match self.approved_connection(ip, &before, &after) {
+            Some((ip, connection)) => Ok(Report {
+                ip,
+                outcome: Outcome::Approved { connection },
+                alerts,
+            }),
+            None => Err(Error::NotApplied {
+                action: "approve",
+                ip,
+                alerts,
+            }),
         }
-        match response.url().path() {
-            INDEX_PATH => Ok(ip),
-            other => Err(Error::Other(anyhow!(
-                "Unexpected URL path in approve response {other}"
-            ))),
+    }
+
+    /// Whether an approval turned the connection `before` into `after`. A forced approval of an
+    /// active IP address is only applied once its validity changes.
+    fn approval_applied(before: &Connection, after: &Connection) -> bool {
+        after.is_active() && after.valid_till != before.valid_till
+    }
+
+    /// Finds the connection that an approval of `ip` applied, comparing the connection tables
+    /// from before and after it. The portal approves the address it sees the request come from,
+    /// which on dual-stack networks may be of the other family than the system IP address. So
+    /// unless the local address is configured, the one connection of the configured family
+    /// that the approval applied is taken if `ip` was not approved.
+    fn approved_connection(
+        &self,
+        ip: IpAddr,
+        before: &HashMap<IpAddr, Connection>,
+        after: &HashMap<IpAddr, Connection>,
+    ) -> Option<(IpAddr, Connection)> {
+        let applied = |ip: &IpAddr, connection: &Connection| {
+            Self::approval_applied(&before.get(ip).copied().unwrap_or_default(), connection)
+        };
+        if let Some(connection) = after.get(&ip).filter(|connection| applied(&ip, connection)) {
+            return Some((ip, *connection));
+        }
+        if self.config.local_address.is_some() {
+            return None;
+        }
+        let family = self.config.family;
+        let mut recorded = after.iter().filter(|(ip, connection)| {
+            family.is_none_or(|family| family.matches(ip)) && applied(ip, connection)
+        });
+        match (recorded.next(), recorded.next()) {
+            (Some((ip, connection)), None) => Some((*ip, *connection)),
+            _ => None,
         }
     }
 
-    pub async fn revoke(&self, user: &User, ip: Option<String>) -> Result<IpAddr, Error> {
-        let status = self.status(user).await?;
+    /// Revokes the authorization of an IP address, or the system IP address if none is given.
+    #[tracing::instrument(skip(self, user), fields(user = user.name()))]
+    pub async fn revoke(&self, user: &User, ip: Option<String>) -> Result<Report, Error> {
+        self.timed(Operation::Revoke, self.revoke_ip(user, ip))
+            .await
+    }
+
+    async fn revoke_ip(&self, user: &User, ip: Option<String>) -> Result<Report, Error> {
+        let status = self.fetch_status(user).await?;
 
         let ip = match ip {
-            Some(ip) => ip
-                .parse()
-                .with_context(|| format!("Ip address is malformed {ip}"))?,
-            None => Self::local_ip()?,
+            Some(ip) => ip.parse().map_err(|_| Error::MalformedIp(ip))?,
+            None => status.system_status.ip,
         };
 
-        if !status.is_connection_active(&ip) {
-            return Ok(ip);
+        let outcome = match status.connection(&ip) {
+            None => Some(Outcome::NotRegistered),
+            Some(connection) if !connection.is_active() => Some(Outcome::AlreadyInactive),
+            Some(_) => None,
+        };
+        if let Some(outcome) = outcome {
+            return Ok(Report {
+                ip,
+                outcome,
+                alerts: status.alerts,
+            });
         }
 
-        let response = self
-            .client
-            .post(format!("{URL}{REVOKE_PATH}/{ip}"))
-            .send()
+        let posted = self
+            .post(
+                user,
+                self.client.post(Self::revoke_url(&self.base_url, &ip)),
+                |status| !status.connection(&ip).is_some_and(Connection::is_active),
+            )
             .await?;
+        let response = match posted {
+            Posted::Response(response) => response,
+            Posted::Applied(status) => {
+                return Ok(Report {
+                    ip,
+                    outcome: Outcome::Revoked,
+                    alerts: status.alerts,
+                })
+            }
+        };
 
+        let (connections, alerts) = self.redirected_connections(response, "revoke").await?;
+        if connections.get(&ip).is_some_and(Connection::is_active) {
+            Err(Error::NotApplied {
+                action: "revoke",
+                ip,
+                alerts,
+            })
+        } else {
+            Ok(Report {
+                ip,
+                outcome: Outcome::Revoked,
+                alerts,
+            })
+        }
+    }
+
+    /// URL revoking the IP address, with the colons of IPv6 addresses percent encoded so that
+    /// they stay within a single path segment.
+    fn revoke_url(base_url: &str, ip: &IpAddr) -> String {
+        format!(
+            "{base_url}{REVOKE_PATH}/{}",
+            ip.to_string().replace(':', "%3A")
+        )
+    }
+
+    /// Parses the connection table and alerts from the index page an approve or revoke request
+    /// redirects to, so that the new state of the connections can be verified.
+    async fn redirected_connections(
+        &self,
+        response: Response,
+        action: &str,
+    ) -> Result<(HashMap<IpAddr, Connection>, Vec<String>), Error> {
         if !response.status().is_success() {
-            return Err(Error::Other(anyhow!(
-                "Revoke response failed with status {}",
+            return Err(Error::UnexpectedResponse(format!(
+                "{action} response failed with status {}",
                 response.status()
             )));
         }
         match response.url().path() {
-            INDEX_PATH => Ok(ip),
-            other => Err(Error::Other(anyhow!(
-                "Unexpected URL path in revoke response {other}"
+            INDEX_PATH => {
+                let html = Html::parse_document(&self.page_text(response).await?);
+                let connections = Self::parse_connections(&html, self.time_now())?;
+                Ok((connections, Self::parse_alerts(&html)))
+            }
+            other => Err(Error::UnexpectedResponse(format!(
+                "Unexpected URL path in {action} response {other}"
             ))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::fixture, tls::TlsConfig};
+    use std::{net::Ipv4Addr, sync::atomic::Ordering};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    fn time_now() -> DateTime<FixedOffset> {
+        INDIA_TZ
+            .with_ymd_and_hms(2023, 7, 23, 10, 7, 0)
+            .single()
+            .expect("Valid datetime")
+    }
+
+    #[test]
+    fn connections_time_left() {
+        let connections =
+            AccountManager::parse_connections(&fixture!("index_many"), time_now()).unwrap();
+        assert_eq!(connections.len(), 4);
+
+        let active = connections[&[10, 21, 0, 17].into()];
+        assert!(active.is_active());
+        assert_eq!(active.time_left, chrono::Duration::days(1));
+        assert_eq!(
+            active.valid_till.map(|valid_till| valid_till.to_rfc3339()),
+            Some(String::from("2023-07-24T10:07:00+05:30"))
+        );
+
+        let inactive = connections[&[10, 42, 3, 201].into()];
+        assert!(!inactive.is_active());
+
+        // Marked active by the portal but already past its validity
+        let expired = connections[&[10, 21, 7, 4].into()];
+        assert!(!expired.is_active());
+        assert!(expired.time_left.is_zero());
+    }
+
+    #[test]
+    fn login_failure_alerts() {
+        assert_eq!(
+            AccountManager::parse_alerts(&fixture!("login_failure")),
+            vec!["Invalid username or password"]
+        );
+    }
+
+    #[test]
+    fn redirect_alerts() {
+        assert_eq!(
+            AccountManager::parse_alerts(&fixture!("approve_redirect")),
+            vec!["IP authorized successfully"]
+        );
+        assert_eq!(
+            AccountManager::parse_alerts(&fixture!("revoke_redirect")),
+            vec!["IP access revoked"]
+        );
+        assert_eq!(
+            AccountManager::parse_alerts(&fixture!("approve_quota_exceeded")),
+            vec!["Monthly quota exceeded, approval is not possible"]
+        );
+        assert!(AccountManager::parse_alerts(&fixture!("index_single")).is_empty());
+    }
+
+    #[test]
+    fn durations() {
+        let durations = AccountManager::parse_durations(&fixture!("index_single"));
+        assert_eq!(
+            durations,
+            vec![
+                DurationOption {
+                    value: String::from("1"),
+                    label: String::from("60 minutes"),
+                    is_default: true,
+                },
+                DurationOption {
+                    value: String::from("2"),
+                    label: String::from("1 day"),
+                    is_default: false,
+                },
+                DurationOption {
+                    value: String::from("3"),
+                    label: String::from("1 month"),
+                    is_default: false,
+                },
+            ]
+        );
+
+        let status = Status {
+            system_status: SystemStatus {
+                ip: [10, 21, 0, 17].into(),
+                connection: Connection::default(),
+                clock_skew: chrono::Duration::zero(),
+            },
+            system_registered: false,
+            connections: HashMap::new(),
+            alerts: vec![],
+            durations,
+        };
+        assert_eq!(status.duration(None).unwrap().value, "1");
+        assert_eq!(status.duration(Some(" 1  DAY ")).unwrap().value, "2");
+        assert_eq!(status.duration(Some("3")).unwrap().label, "1 month");
+        assert_eq!(status.duration(Some("hour")).unwrap().value, "1");
+        assert_eq!(status.duration(Some("Day")).unwrap().value, "2");
+        assert_eq!(status.duration(Some("month")).unwrap().value, "3");
+        assert!(matches!(
+            status.duration(Some("1 year")),
+            Err(Error::UnknownDuration { available, .. }) if available.len() == 3
+        ));
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let base = std::time::Duration::from_millis(500);
+        assert_eq!(backoff(base, 1), base);
+        assert_eq!(backoff(base, 3), base * 4);
+        assert_eq!(
+            backoff(std::time::Duration::MAX, 2),
+            std::time::Duration::MAX
+        );
+    }
+
+    #[test]
+    fn revoke_url() {
+        assert_eq!(
+            AccountManager::revoke_url(URL, &[10, 21, 0, 17].into()),
+            "https://netaccess.iitm.ac.in/account/revoke/10.21.0.17"
+        );
+        let ip = std::net::Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1f).into();
+        let url = AccountManager::revoke_url(URL, &ip);
+        assert_eq!(
+            url,
+            "https://netaccess.iitm.ac.in/account/revoke/2001%3Adb8%3A%3A1f"
+        );
+        assert_eq!(
+            Url::parse(&url)
+                .unwrap()
+                .path_segments()
+                .unwrap()
+                .next_back(),
+            Some("2001%3Adb8%3A%3A1f")
+        );
+    }
+
+    #[test]
+    fn revoke_redirect_inactive() {
+        let connections =
+            AccountManager::parse_connections(&fixture!("revoke_redirect"), time_now()).unwrap();
+        assert!(!connections[&[10, 21, 0, 17].into()].is_active());
+    }
+
+    /// How the local portal answers an approval.
+    #[derive(Debug, Clone, Copy)]
+    enum Approval {
+        /// Applies it and redirects to the index page.
+        Apply,
+        /// Applies it but never responds in time.
+        ApplyAndHang,
+        /// Fails with a server error without applying it.
+        Fail,
+        /// Redirects to the index page without applying it.
+        Ignore,
+    }
+
+    /// Serves the index page and approvals like the portal, with 127.0.0.1 as the only
+    /// connection once approved. Returns the base URL and the number of approvals posted.
+    async fn serve_portal(approvals: Vec<Approval>) -> (String, Arc<AtomicUsize>) {
+        serve_portal_recording(approvals, "127.0.0.1").await
+    }
+
+    /// Serves the portal like [`serve_portal`], recording the given address once approved.
+    async fn serve_portal_recording(
+        approvals: Vec<Approval>,
+        recorded: &'static str,
+    ) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let posted = Arc::new(AtomicUsize::default());
+        let approved = Arc::new(Mutex::new(None));
+        let counter = Arc::clone(&posted);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (approvals, posted, approved) = (
+                    approvals.clone(),
+                    Arc::clone(&counter),
+                    Arc::clone(&approved),
+                );
+                tokio::spawn(async move {
+                    respond(stream, &approvals, recorded, &posted, &approved).await;
+                });
+            }
+        });
+        (url, posted)
+    }
+
+    async fn respond(
+        mut stream: TcpStream,
+        approvals: &[Approval],
+        recorded: &str,
+        posted: &AtomicUsize,
+        approved: &Mutex<Option<String>>,
+    ) {
+        // Requests are read up to the end of their headers, which is all that is needed
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let Ok(read @ 1..) = stream.read(&mut buf).await else {
+                return;
+            };
+            request.extend_from_slice(&buf[..read]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let page = |approved: &Option<String>| match approved {
+            Some(valid_till) => include_str!("../tests/fixtures/index_single.html")
+                .replace("10.21.0.17", recorded)
+                .replace("24 Jul 2023, 10:07", valid_till),
+            None => String::from(include_str!("../tests/fixtures/index_empty.html")),
+        };
+        let (status, body) = if request.starts_with(&format!("GET {INDEX_PATH} ")) {
+            ("200 OK", page(&approved.lock().unwrap()))
+        } else if request.starts_with(&format!("POST {APPROVE_PATH} ")) {
+            let count = posted.fetch_add(1, Ordering::SeqCst);
+            let approval = approvals[count.min(approvals.len() - 1)];
+            if !matches!(approval, Approval::Fail | Approval::Ignore) {
+                let valid_till = (Utc::now() + chrono::Duration::days(1))
+                    .with_timezone(&*INDIA_TZ)
+                    .format("%d %b %Y, %H:%M")
+                    .to_string();
+                *approved.lock().unwrap() = Some(valid_till);
+            }
+            match approval {
+                Approval::Apply | Approval::Ignore => {
+                    ("302 Found\r\nLocation: /account/index", String::new())
+                }
+                Approval::ApplyAndHang => {
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                    return;
+                }
+                Approval::Fail => ("500 Internal Server Error", String::new()),
+            }
+        } else {
+            ("404 Not Found", String::new())
+        };
+        let _ = stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 {status}\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await;
+    }
+
+    fn local_config() -> HttpConfig {
+        HttpConfig {
+            timeout: std::time::Duration::from_millis(500),
+            retry_backoff: std::time::Duration::from_millis(10),
+            local_address: Some(Ipv4Addr::LOCALHOST.into()),
+            tls: TlsConfig {
+                ca_bundles: vec![PathBuf::from(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/tests/fixtures/portal_cert.pem"
+                ))],
+                pins: vec![],
+            },
+            ..Default::default()
+        }
+    }
+
+    fn local_account_manager(url: String) -> AccountManager {
+        AccountManager::with_config(local_config())
+            .unwrap()
+            .with_base_url(url)
+    }
+
+    fn user() -> User {
+        User::new(String::from("XX19X001"), String::from("password"))
+    }
+
+    #[tokio::test]
+    async fn approval_applied_despite_timeout() {
+        let (url, posted) = serve_portal(vec![Approval::ApplyAndHang]).await;
+        let report = local_account_manager(url)
+            .approve(&user(), None, false)
+            .await
+            .unwrap();
+
+        assert!(
+            matches!(report.outcome, Outcome::Approved { connection } if connection.is_active())
+        );
+        assert_eq!(posted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn approval_posted_again_after_server_error() {
+        let (url, posted) = serve_portal(vec![Approval::Fail, Approval::Apply]).await;
+        let report = local_account_manager(url)
+            .approve(&user(), None, false)
+            .await
+            .unwrap();
+
+        assert!(
+            matches!(report.outcome, Outcome::Approved { connection } if connection.is_active())
+        );
+        assert_eq!(posted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn forced_approval_ignored_by_portal() {
+        let (url, posted) = serve_portal(vec![Approval::Apply, Approval::Ignore]).await;
+        let account_manager = local_account_manager(url);
+        account_manager.approve(&user(), None, false).await.unwrap();
+        let result = account_manager.approve(&user(), None, true).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::NotApplied {
+                action: "approve",
+                ..
+            })
+        ));
+        assert_eq!(posted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn redirects_traced_per_request() {
+        let (url, _) = serve_portal(vec![Approval::Apply]).await;
+        let path = std::env::temp_dir().join(format!("netaccess-trace-{}", std::process::id()));
+        let account_manager =
+            local_account_manager(url).with_http_trace(HttpTrace::create(&path).unwrap());
+        let user = user();
+        let (approved, status) = tokio::join!(
+            account_manager.approve(&user, None, false),
+            account_manager.status(&user),
+        );
+        approved.unwrap();
+        status.unwrap();
+
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let redirects = trace
+            .lines()
+            .filter(|line| line.starts_with("< redirects: "))
+            .collect::<Vec<_>>();
+        assert_eq!(redirects.len(), 1);
+        assert!(redirects[0].contains(&format!("{APPROVE_PATH} -> ")));
+        assert!(redirects[0].ends_with(INDEX_PATH));
+    }
+
+    #[tokio::test]
+    async fn dual_stack_approval_of_recorded_address() {
+        // The portal records the IPv6 address the request comes from, while the system IP
+        // address found before approving is an IPv4 one
+        for approval in [Approval::Apply, Approval::ApplyAndHang] {
+            let (url, posted) = serve_portal_recording(vec![approval], "2001:db8::17").await;
+            let report = AccountManager::with_config(HttpConfig {
+                local_address: None,
+                ..local_config()
+            })
+            .unwrap()
+            .with_base_url(url)
+            .approve(&user(), None, false)
+            .await
+            .unwrap();
+
+            assert_eq!(report.ip, "2001:db8::17".parse::<IpAddr>().unwrap());
+            assert!(
+                matches!(report.outcome, Outcome::Approved { connection } if connection.is_active())
+            );
+            assert_eq!(posted.load(Ordering::SeqCst), 1);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use chrono::NaiveDateTime;
+use scraper::{ElementRef, Html, Selector};
+use std::net::IpAddr;
+
+/*
+<table>
+    <tbody>
+        <tr>
+            <th>MAC</th>
+            <th align="center">IP</th>
+            <th>Valid till</th>
+            <th>Download today</th>
+            <th colspan="2">Status</th>
+        </tr>
+        <tr>
+            <td>
+                <E:XP:IR:ED:>
+            </td>
+            <td>XX.XX.XX.XXX</td>
+            <td>24 Jul 2023, 10:07</td>
+            <td>     0 B</td>
+            <td><span class='label label-success'>Active</span></td>
+            <td><a href="/account/revoke/XX.XX.XX.XXX"><span class='label label-danger'>Delete</span></a></td>
+        </tr>
+    </tbody>
+</table>
+ */
+
+const MAC_HEADER: &str = "MAC";
+const IP_HEADER: &str = "IP";
+const VALID_TILL_HEADER: &str = "Valid till";
+const DOWNLOAD_HEADER: &str = "Download today";
+const STATUS_HEADER: &str = "Status";
+
+/// Headers identifying the connections table, of which only a few are required for parsing.
+const HEADERS: [&str; 5] = [
+    MAC_HEADER,
+    IP_HEADER,
+    VALID_TILL_HEADER,
+    DOWNLOAD_HEADER,
+    STATUS_HEADER,
+];
+const REQUIRED_HEADERS: [&str; 3] = [IP_HEADER, VALID_TILL_HEADER, STATUS_HEADER];
+
+const VALID_TILL_FORMAT: &str = "%d %b %Y, %H:%M";
+const ACTIVE_STATUS: &str = "Active";
+
+lazy_static::lazy_static! {
+    static ref TABLE_SELECTOR: Selector =
+        Selector::parse("table").expect("Failed to create table selector");
+    static ref TR_SELECTOR: Selector =
+        Selector::parse("tr").expect("Failed to create tr selector");
+    static ref TH_SELECTOR: Selector =
+        Selector::parse("th").expect("Failed to create th selector");
+    static ref TD_SELECTOR: Selector =
+        Selector::parse("td").expect("Failed to create td selector");
+}
+
+/// Describes how a portal page deviates from the expected layout.
+#[derive(thiserror::Error, Debug)]
+pub enum LayoutError {
+    /// No table with any of the expected headers was found.
+    #[error("no connections table with headers {HEADERS:?} found, tables on page have headers {found:?}")]
+    MissingTable {
+        /// Headers of every table on the page.
+        found: Vec<Vec<String>>,
+    },
+    /// The connections table lacks some of the columns required for parsing.
+    #[error("connections table is missing columns {missing:?}, found headers {found:?}")]
+    MissingColumns {
+        /// Required headers that were not found.
+        missing: Vec<&'static str>,
+        /// Headers of the connections table.
+        found: Vec<String>,
+    },
+    /// A row of the connections table has no cell for a column.
+    #[error("row {row} of connections table has no {column} cell")]
+    MissingCell {
+        /// One based index of the row.
+        row: usize,
+        /// Header of the column.
+        column: &'static str,
+    },
+    /// A cell of the connections table could not be parsed.
+    #[error("row {row} of connections table has malformed {column} {value:?}: {reason}")]
+    MalformedCell {
+        /// One based index of the row.
+        row: usize,
+        /// Header of the column.
+        column: &'static str,
+        /// Text content of the cell.
+        value: String,
+        /// Why the value could not be parsed.
+        reason: String,
+    },
+    /// The approve form offers no durations.
+    #[error("approve form has no duration options")]
+    MissingDurations,
+}
+
+/// A single row of the connections table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Row {
+    pub ip: IpAddr,
+    pub valid_till: NaiveDateTime,
+    pub is_active: bool,
+}
+
+/// Positions of the required columns, in units of cells with colspan taken into account.
+struct Columns {
+    ip: usize,
+    valid_till: usize,
+    status: usize,
+}
+
+/// Text content of an element including nested tags, with whitespace collapsed.
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn colspan(element: ElementRef) -> usize {
+    element
+        .value()
+        .attr("colspan")
+        .and_then(|span| span.trim().parse().ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Cells of a row keyed by the position they start at, accounting for colspan.
+fn cells<'a>(tr: ElementRef<'a>, selector: &Selector) -> Vec<(usize, ElementRef<'a>)> {
+    let mut position = 0;
+    tr.select(selector)
+        .map(|cell| {
+            let start = position;
+            position += colspan(cell);
+            (start, cell)
+        })
+        .collect()
+}
+
+fn header_row(table: ElementRef) -> Option<ElementRef> {
+    table
+        .select(&TR_SELECTOR)
+        .find(|tr| tr.select(&TH_SELECTOR).next().is_some())
+}
+
+fn headers(tr: ElementRef) -> Vec<(usize, String)> {
+    cells(tr, &TH_SELECTOR)
+        .into_iter()
+        .map(|(position, th)| (position, text(th)))
+        .collect()
+}
+
+fn position(headers: &[(usize, String)], name: &str) -> Option<usize> {
+    headers
+        .iter()
+        .find(|(_, header)| header.eq_ignore_ascii_case(name))
+        .map(|(position, _)| *position)
+}
+
+fn locate_table(html: &Html) -> Result<(ElementRef<'_>, Columns), LayoutError> {
+    let tables = html
+        .select(&TABLE_SELECTOR)
+        .filter_map(|table| header_row(table).map(|tr| (table, headers(tr))))
+        .collect::<Vec<_>>();
+
+    let matches = |headers: &[(usize, String)]| {
+        HEADERS
+            .iter()
+            .filter(|name| position(headers, name).is_some())
+            .count()
+    };
+    let Some((table, headers)) = tables
+        .iter()
+        .filter(|(_, headers)| matches(headers) > 0)
+        .max_by_key(|(_, headers)| matches(headers))
+    else {
+        return Err(LayoutError::MissingTable {
+            found: tables
+                .into_iter()
+                .map(|(_, headers)| headers.into_iter().map(|(_, header)| header).collect())
+                .collect(),
+        });
+    };
+
+    let missing = REQUIRED_HEADERS
+        .into_iter()
+        .filter(|name| position(headers, name).is_none())
+        .collect::<Vec<_>>();
+    match (
+        position(headers, IP_HEADER),
+        position(headers, VALID_TILL_HEADER),
+        position(headers, STATUS_HEADER),
+    ) {
+        (Some(ip), Some(valid_till), Some(status)) => Ok((
+            *table,
+            Columns {
+                ip,
+                valid_till,
+                status,
+            },
+        )),
+        _ => Err(LayoutError::MissingColumns {
+            missing,
+            found: headers.iter().map(|(_, header)| header.clone()).collect(),
+        }),
+    }
+}
+
+fn cell_text(
+    cells: &[(usize, ElementRef)],
+    row: usize,
+    position: usize,
+    column: &'static str,
+) -> Result<String, LayoutError> {
+    cells
+        .iter()
+        .find(|(start, _)| *start == position)
+        .map(|(_, cell)| text(*cell))
+        .ok_or(LayoutError::MissingCell { row, column })
+}
+
+fn parse_row(
+    cells: &[(usize, ElementRef)],
+    row: usize,
+    columns: &Columns,
+) -> Result<Row, LayoutError> {
+    let malformed = |column, value: &str, reason: String| LayoutError::MalformedCell {
+        row,
+        column,
+        value: value.to_owned(),
+        reason,
+    };
+
+    let ip = cell_text(cells, row, columns.ip, IP_HEADER)?;
+    let ip = ip
+        .parse()
+        .map_err(|err| malformed(IP_HEADER, &ip, format!("{err}")))?;
+
+    let valid_till = cell_text(cells, row, columns.valid_till, VALID_TILL_HEADER)?;
+    let valid_till = NaiveDateTime::parse_from_str(&valid_till, VALID_TILL_FORMAT)
+        .map_err(|err| malformed(VALID_TILL_HEADER, &valid_till, format!("{err}")))?;
+
+    let status = cell_text(cells, row, columns.status, STATUS_HEADER)?;
+
+    Ok(Row {
+        ip,
+        valid_till,
+        is_active: status.eq_ignore_ascii_case(ACTIVE_STATUS),
+    })
+}
+
+/// Parses the connections table of the portal index page. The table is located by its header
+/// row and columns are mapped by name, so reordered or additional columns are tolerated.
+pub(crate) fn parse(html: &Html) -> Result<Vec<Row>, LayoutError> {
+    let (table, columns) = locate_table(html)?;
+    table
+        .select(&TR_SELECTOR)
+        .map(|tr| cells(tr, &TD_SELECTOR))
+        // Header rows have no td elements, while placeholder rows such as "No records" span
+        // the whole table with a single cell.
+        .filter(|cells| match cells.as_slice() {
+            [] => false,
+            [(_, cell)] => colspan(*cell) == 1,
+            _ => true,
+        })
+        .enumerate()
+        .map(|(index, cells)| parse_row(&cells, index + 1, &columns))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fixture;
+    use chrono::NaiveDate;
+    use std::net::Ipv6Addr;
+
+    fn datetime(day: u32, month: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2023, month, day)
+            .and_then(|date| date.and_hms_opt(hour, min, 0))
+            .expect("Valid datetime")
+    }
+
+    #[test]
+    fn empty_table() {
+        assert_eq!(parse(&fixture!("index_empty")).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn single_row() {
+        assert_eq!(
+            parse(&fixture!("index_single")).unwrap(),
+            vec![Row {
+                ip: [10, 21, 0, 17].into(),
+                valid_till: datetime(24, 7, 10, 7),
+                is_active: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn many_rows() {
+        let rows = parse(&fixture!("index_many")).unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[1],
+            Row {
+                ip: [10, 42, 3, 201].into(),
+                valid_till: datetime(23, 7, 8, 30),
+                is_active: false,
+            }
+        );
+        assert_eq!(
+            rows[2],
+            Row {
+                ip: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x1f).into(),
+                valid_till: datetime(25, 8, 23, 59),
+                is_active: true,
+            }
+        );
+    }
+
+    #[test]
+    fn reordered_columns_with_nested_tags() {
+        assert_eq!(
+            parse(&fixture!("index_reordered")).unwrap(),
+            vec![Row {
+                ip: [10, 21, 0, 17].into(),
+                valid_till: datetime(24, 7, 10, 7),
+                is_active: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn malformed_date() {
+        let err = parse(&fixture!("index_malformed_date")).unwrap_err();
+        assert!(matches!(
+            err,
+            LayoutError::MalformedCell {
+                row: 1,
+                column: VALID_TILL_HEADER,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn missing_columns() {
+        let err = parse(&fixture!("index_layout_changed")).unwrap_err();
+        let LayoutError::MissingColumns { missing, found } = err else {
+            panic!("Unexpected error {err:?}");
+        };
+        assert_eq!(missing, vec![VALID_TILL_HEADER, STATUS_HEADER]);
+        assert_eq!(found, vec![MAC_HEADER, IP_HEADER, DOWNLOAD_HEADER]);
+    }
+
+    #[test]
+    fn missing_table() {
+        let err = parse(&fixture!("login_failure")).unwrap_err();
+        assert!(matches!(err, LayoutError::MissingTable { found } if found.is_empty()));
+    }
+}
@@ -0,0 +1,211 @@
+use reqwest::{redirect::Policy, Client, ClientBuilder, Url};
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+
+/// Timeout of a single probe request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay between probes while waiting for connectivity.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Error returned when none of the probe targets are reachable, along with why each failed.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("Internet is unreachable, {}", fmt_failures(.failures))]
+pub struct Unreachable {
+    /// Targets probed along with the reason they were considered unreachable.
+    pub failures: Vec<(Url, String)>,
+}
+
+fn fmt_failures(failures: &[(Url, String)]) -> String {
+    if failures.is_empty() {
+        return String::from("no probe targets configured");
+    }
+    failures
+        .iter()
+        .map(|(target, reason)| format!("{target} {reason}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Why a single probe target was considered unreachable.
+enum Failure {
+    Request(reqwest::Error),
+    Redirected(Option<String>),
+    Status(reqwest::StatusCode),
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(err) => write!(f, "failed: {err}"),
+            Self::Redirected(Some(location)) => write!(f, "redirected to {location}"),
+            Self::Redirected(None) => "redirected".fmt(f),
+            Self::Status(status) => write!(f, "responded with {status}"),
+        }
+    }
+}
+
+/// Checks whether the internet is actually reachable by sending HEAD requests to well known
+/// targets, independent of what the portal reports. Redirects are not followed, as captive
+/// portals redirect requests to their login page while access is blocked.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    client: Client,
+    targets: Vec<Url>,
+}
+
+impl Probe {
+    /// Target responding with no content whenever the internet is reachable.
+    pub const DEFAULT_TARGET: &'static str = "http://connectivitycheck.gstatic.com/generate_204";
+
+    /// Creates a probe of the given targets, of which any one responding successfully is enough.
+    pub fn new(targets: Vec<Url>) -> reqwest::Result<Self> {
+        ClientBuilder::default()
+            .redirect(Policy::none())
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map(|client| Self { client, targets })
+    }
+
+    /// Targets probed in order.
+    pub fn targets(&self) -> &[Url] {
+        &self.targets
+    }
+
+    async fn probe(&self, target: &Url) -> Result<(), Failure> {
+        let response = self
+            .client
+            .head(target.clone())
+            .send()
+            .await
+            .map_err(Failure::Request)?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .map(String::from);
+            return Err(Failure::Redirected(location));
+        }
+        if !status.is_success() {
+            return Err(Failure::Status(status));
+        }
+        Ok(())
+    }
+
+    /// Probes the targets in order, returning the first one that responds successfully.
+    pub async fn check(&self) -> Result<&Url, Unreachable> {
+        let mut failures = vec![];
+        for target in &self.targets {
+            match self.probe(target).await {
+                Ok(()) => return Ok(target),
+                Err(failure) => failures.push((target.clone(), failure.to_string())),
+            }
+        }
+        Err(Unreachable { failures })
+    }
+
+    /// Probes repeatedly until a target responds successfully or the timeout elapses, returning
+    /// the failures of the last attempt in the latter case.
+    pub async fn wait(&self, timeout: Duration) -> Result<&Url, Unreachable> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let result = self.check().await;
+            if result.is_ok() || Instant::now() + RETRY_INTERVAL > deadline {
+                return result;
+            }
+            time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Serves every request on a local port with the responses in turn, repeating the last one,
+    /// returning the URL of the server along with the number of requests served.
+    pub(crate) async fn serve(responses: &[&'static str]) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/generate_204", listener.local_addr().unwrap());
+        let responses = responses.to_vec();
+        let served = Arc::new(AtomicUsize::default());
+        let served_clone = Arc::clone(&served);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let count = served_clone.fetch_add(1, Ordering::SeqCst);
+                let response = responses[count.min(responses.len() - 1)];
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(format!("{response}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+                    .await;
+            }
+        });
+        (url.parse().unwrap(), served)
+    }
+
+    /// URL of a local port nothing listens on.
+    async fn closed_url() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        url.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reachable() {
+        let (url, _) = serve(&["HTTP/1.1 204 No Content"]).await;
+        let probe = Probe::new(vec![closed_url().await, url.clone()]).unwrap();
+        assert_eq!(probe.check().await.unwrap(), &url);
+    }
+
+    #[tokio::test]
+    async fn captive_portal_redirect() {
+        let (url, _) =
+            serve(&["HTTP/1.1 302 Found\r\nLocation: https://netaccess.iitm.ac.in/"]).await;
+        let probe = Probe::new(vec![url.clone()]).unwrap();
+        let Unreachable { failures } = probe.check().await.unwrap_err();
+        assert_eq!(
+            failures,
+            vec![(
+                url,
+                String::from("redirected to https://netaccess.iitm.ac.in/")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn unreachable() {
+        let (url, _) = serve(&["HTTP/1.1 503 Service Unavailable"]).await;
+        let probe = Probe::new(vec![closed_url().await, url]).unwrap();
+        let err = probe.check().await.unwrap_err();
+        assert_eq!(err.failures.len(), 2);
+        assert!(err.failures[0].1.starts_with("failed"));
+        assert_eq!(err.failures[1].1, "responded with 503 Service Unavailable");
+    }
+
+    #[tokio::test]
+    async fn waits_for_connectivity() {
+        let (url, served) = serve(&[
+            "HTTP/1.1 302 Found",
+            "HTTP/1.1 302 Found",
+            "HTTP/1.1 204 No Content",
+        ])
+        .await;
+        let probe = Probe::new(vec![url]).unwrap();
+        probe.wait(Duration::from_secs(30)).await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+}
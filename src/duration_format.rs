@@ -0,0 +1,178 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+
+/// Style used to format durations such as the time left of a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationStyle {
+    /// Spelled out units, such as `2 days, 3 hours`.
+    #[default]
+    Long,
+    /// Abbreviated units, such as `2d 3h`.
+    Compact,
+    /// ISO 8601 duration, such as `P2DT3H`.
+    Iso8601,
+}
+
+impl DurationStyle {
+    /// All styles, in the order they are listed to users.
+    pub const ALL: [Self; 3] = [Self::Long, Self::Compact, Self::Iso8601];
+
+    /// Name of the style as accepted by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Long => "long",
+            Self::Compact => "compact",
+            Self::Iso8601 => "iso",
+        }
+    }
+
+    /// Formats a duration in this style. Durations under an hour include seconds, longer ones
+    /// are truncated to minutes. Negative durations are prefixed with a minus sign.
+    pub fn format(self, duration: &chrono::Duration) -> String {
+        self.format_seconds(
+            *duration < chrono::Duration::zero(),
+            duration.num_seconds().abs(),
+        )
+    }
+
+    /// Formats a standard library duration in this style, such as the time until a wake up.
+    pub fn format_std(self, duration: &std::time::Duration) -> String {
+        self.format_seconds(false, i64::try_from(duration.as_secs()).unwrap_or(i64::MAX))
+    }
+
+    fn format_seconds(self, negative: bool, total: i64) -> String {
+        let sign = if negative { "-" } else { "" };
+        let units = [
+            (total / DAY, "day", "d"),
+            (total % DAY / HOUR, "hour", "h"),
+            (total % HOUR / MINUTE, "minute", "m"),
+            (if total < HOUR { total % MINUTE } else { 0 }, "second", "s"),
+        ];
+
+        if self == Self::Iso8601 {
+            let [days, hours, minutes, seconds] = units.map(|(value, _, _)| value);
+            let mut iso = format!("{sign}P");
+            if days > 0 {
+                iso.push_str(&format!("{days}D"));
+            }
+            if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+                iso.push('T');
+            }
+            for (value, unit) in [(hours, 'H'), (minutes, 'M'), (seconds, 'S')] {
+                if value > 0 {
+                    iso.push_str(&format!("{value}{unit}"));
+                }
+            }
+            if total == 0 {
+                iso.push_str("0S");
+            }
+            return iso;
+        }
+
+        let mut fragments = units
+            .into_iter()
+            .filter(|(value, _, _)| *value > 0)
+            .map(|(value, name, abbreviation)| match self {
+                Self::Compact => format!("{value}{abbreviation}"),
+                _ if value == 1 => format!("{value} {name}"),
+                _ => format!("{value} {name}s"),
+            })
+            .collect::<Vec<_>>();
+        if fragments.is_empty() {
+            fragments.push(match self {
+                Self::Compact => String::from("0s"),
+                _ => String::from("0 seconds"),
+            });
+        }
+        let separator = if self == Self::Compact { " " } else { ", " };
+        format!("{sign}{}", fragments.join(separator))
+    }
+}
+
+impl Display for DurationStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+/// Error returned when parsing an unknown [`DurationStyle`] name.
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown duration style {0}, expected one of long, compact or iso")]
+pub struct UnknownDurationStyle(String);
+
+impl FromStr for DurationStyle {
+    type Err = UnknownDurationStyle;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|style| style.name() == s)
+            .ok_or_else(|| UnknownDurationStyle(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(style: DurationStyle, seconds: i64) -> String {
+        style.format(&chrono::Duration::seconds(seconds))
+    }
+
+    #[test]
+    fn long() {
+        assert_eq!(format(DurationStyle::Long, 0), "0 seconds");
+        assert_eq!(format(DurationStyle::Long, 1), "1 second");
+        assert_eq!(format(DurationStyle::Long, 59), "59 seconds");
+        assert_eq!(format(DurationStyle::Long, 61), "1 minute, 1 second");
+        assert_eq!(format(DurationStyle::Long, HOUR + 59), "1 hour");
+        assert_eq!(
+            format(DurationStyle::Long, 2 * DAY + 3 * HOUR + 1),
+            "2 days, 3 hours"
+        );
+        assert_eq!(format(DurationStyle::Long, -90), "-1 minute, 30 seconds");
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(format(DurationStyle::Compact, 0), "0s");
+        assert_eq!(format(DurationStyle::Compact, 45), "45s");
+        assert_eq!(format(DurationStyle::Compact, 2 * DAY + 3 * HOUR), "2d 3h");
+        assert_eq!(format(DurationStyle::Compact, DAY + 5 * MINUTE), "1d 5m");
+        assert_eq!(format(DurationStyle::Compact, -5 * MINUTE), "-5m");
+    }
+
+    #[test]
+    fn iso8601() {
+        assert_eq!(format(DurationStyle::Iso8601, 0), "PT0S");
+        assert_eq!(format(DurationStyle::Iso8601, 45), "PT45S");
+        assert_eq!(format(DurationStyle::Iso8601, 2 * DAY), "P2D");
+        assert_eq!(format(DurationStyle::Iso8601, 2 * DAY + 3 * HOUR), "P2DT3H");
+        assert_eq!(format(DurationStyle::Iso8601, -90), "-PT1M30S");
+    }
+
+    #[test]
+    fn std_duration() {
+        assert_eq!(
+            DurationStyle::Compact.format_std(&std::time::Duration::from_secs(300)),
+            "5m"
+        );
+        assert!(DurationStyle::Long
+            .format_std(&std::time::Duration::MAX)
+            .starts_with("106751991167300 days"));
+    }
+
+    #[test]
+    fn parse() {
+        for style in DurationStyle::ALL {
+            assert_eq!(style.name().parse::<DurationStyle>().unwrap(), style);
+        }
+        assert!("short".parse::<DurationStyle>().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use netaccess::Outcome;
+use std::process;
+
+/// Process exit codes reported by the application, so that scripts can react to the outcome of
+/// a command without parsing its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitCode {
+    /// The command completed successfully.
+    Success = 0,
+    /// An error that does not fall in any of the other categories.
+    Failure = 1,
+    /// Invalid command line arguments, reported by clap.
+    Usage = 2,
+    /// The portal rejected the user credentials.
+    InvalidCredentials = 3,
+    /// The portal could not be reached, either due to a failed connection or a timeout.
+    PortalUnreachable = 4,
+    /// The portal responded with an unexpected status or redirect.
+    PortalError = 5,
+    /// A portal page could not be parsed, likely because its layout changed.
+    PortalChanged = 6,
+    /// The local IP address of the system could not be determined.
+    LocalIp = 7,
+    /// User provided input such as an IP address is malformed.
+    InvalidInput = 8,
+    /// The IP address is not registered with the portal, so there was nothing to revoke.
+    NotRegistered = 9,
+    /// The IP address is already active, so approval was skipped.
+    AlreadyActive = 10,
+    /// The IP address is already inactive, so there was nothing to revoke.
+    AlreadyInactive = 11,
+    /// The portal accepted the request, but the connection table does not reflect it.
+    NotApplied = 12,
+    /// The internet could not be reached through any of the probe targets.
+    InternetUnreachable = 13,
+    /// The portal certificate failed verification against the trusted roots or pins.
+    CertificateRejected = 14,
+    /// The portal offers no TLS protocol version, cipher suite or key exchange that is
+    /// supported, so retrying does not help.
+    TlsIncompatible = 15,
+}
+
+impl ExitCode {
+    /// All exit codes along with a short description, used to document them in the CLI help.
+    pub const ALL: [(Self, &'static str); 16] = [
+        (Self::Success, "success"),
+        (Self::Failure, "unclassified failure"),
+        (Self::Usage, "invalid command line usage"),
+        (Self::InvalidCredentials, "invalid credentials"),
+        (Self::PortalUnreachable, "portal unreachable"),
+        (Self::PortalError, "unexpected portal response"),
+        (Self::PortalChanged, "portal page could not be parsed"),
+        (Self::LocalIp, "local IP address unavailable"),
+        (Self::InvalidInput, "malformed input"),
+        (Self::NotRegistered, "IP address not registered"),
+        (Self::AlreadyActive, "IP address already active"),
+        (Self::AlreadyInactive, "IP address already inactive"),
+        (Self::NotApplied, "request not applied by portal"),
+        (Self::InternetUnreachable, "internet unreachable"),
+        (Self::CertificateRejected, "portal certificate rejected"),
+        (
+            Self::TlsIncompatible,
+            "no TLS cipher suite shared with portal",
+        ),
+    ];
+
+    pub fn help() -> String {
+        Self::ALL
+            .iter()
+            .fold(String::from("Exit codes:\n"), |mut help, (code, desc)| {
+                help.push_str(&format!("  {:<3} {desc}\n", *code as u8));
+                help
+            })
+    }
+}
+
+impl From<&netaccess::Error> for ExitCode {
+    fn from(err: &netaccess::Error) -> Self {
+        use netaccess::Error;
+        match err {
+            err if err.is_certificate_rejected() => Self::CertificateRejected,
+            err if err.is_tls_incompatible() => Self::TlsIncompatible,
+            err if err.is_unreachable() => Self::PortalUnreachable,
+            Error::Tls(netaccess::TlsError::Handshake { .. }) => Self::PortalUnreachable,
+            Error::Reqwest(_) | Error::UnexpectedResponse(_) => Self::PortalError,
+            Error::InvalidCredentials(_) => Self::InvalidCredentials,
+            Error::Parse(_) => Self::PortalChanged,
+            Error::LocalIp(_) => Self::LocalIp,
+            Error::MalformedIp(_) | Error::UnknownDuration { .. } => Self::InvalidInput,
+            Error::NotApplied { .. } => Self::NotApplied,
+            _ => Self::Failure,
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Approved { connection: _ } | Outcome::Revoked => Self::Success,
+            Outcome::AlreadyActive { time_left: _ } => Self::AlreadyActive,
+            Outcome::NotRegistered => Self::NotRegistered,
+            Outcome::AlreadyInactive => Self::AlreadyInactive,
+        }
+    }
+}
+
+impl From<&anyhow::Error> for ExitCode {
+    fn from(err: &anyhow::Error) -> Self {
+        if err.is::<netaccess::Unreachable>() {
+            return Self::InternetUnreachable;
+        }
+        err.downcast_ref::<netaccess::Error>()
+            .map_or(Self::Failure, Self::from)
+    }
+}
+
+impl From<ExitCode> for process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code as u8)
+    }
+}
@@ -0,0 +1,296 @@
+use crate::monitor_tap;
+use chrono::{DateTime, Local};
+use clap::{Args, ValueEnum};
+use netaccess::{State, SystemStatus};
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use serde_json::json;
+use std::{
+    fmt::{self, Display, Formatter},
+    net::IpAddr,
+    path::PathBuf,
+    process::Stdio,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    process::Command,
+    sync::{mpsc, watch},
+    time,
+};
+
+/// Monitor events that run hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookEvent {
+    /// The monitor moved to another state
+    State,
+    /// The system IP address got approved
+    Approved,
+    /// The monitor ran into an error and awaits a retry
+    Error,
+}
+
+impl HookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            Self::State => "state",
+            Self::Approved => "approved",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl Display for HookEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct HookArgs {
+    /// Script to run on monitor events, described by the NETACCESS_EVENT, NETACCESS_STATE,
+    /// NETACCESS_IP, NETACCESS_TIME_LEFT and NETACCESS_ERROR environment variables
+    #[arg(long, value_name = "PATH")]
+    hook_script: Option<PathBuf>,
+
+    /// URL to POST a JSON payload describing monitor events to
+    #[arg(long, value_name = "URL")]
+    webhook: Option<Url>,
+
+    /// Monitor events to run the hook script and webhook for
+    #[arg(
+        long,
+        value_name = "EVENT",
+        value_delimiter = ',',
+        default_values_t = [HookEvent::Approved, HookEvent::Error]
+    )]
+    hook_on: Vec<HookEvent>,
+
+    /// The duration of time in seconds after which a hook is abandoned
+    #[arg(long, default_value_t = 10)]
+    hook_timeout: u64,
+}
+
+impl HookArgs {
+    pub fn is_empty(&self) -> bool {
+        self.hook_script.is_none() && self.webhook.is_none()
+    }
+}
+
+/// Details of a monitor event passed on to hooks.
+struct Payload {
+    event: HookEvent,
+    state: &'static str,
+    ip: Option<IpAddr>,
+    /// Time left of the system IP address in seconds, if active.
+    time_left: Option<i64>,
+    error: Option<String>,
+    time: DateTime<Local>,
+}
+
+impl Payload {
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        vec![
+            ("NETACCESS_EVENT", self.event.name().to_owned()),
+            ("NETACCESS_STATE", self.state.to_owned()),
+            ("NETACCESS_IP", optional(self.ip.map(|ip| ip.to_string()))),
+            (
+                "NETACCESS_TIME_LEFT",
+                optional(self.time_left.map(|time_left| time_left.to_string())),
+            ),
+            ("NETACCESS_ERROR", optional(self.error.clone())),
+        ]
+    }
+
+    fn json(&self) -> String {
+        json!({
+            "event": self.event.name(),
+            "state": self.state,
+            "ip": self.ip,
+            "time_left": self.time_left,
+            "error": self.error,
+            "time": self.time.to_rfc3339(),
+        })
+        .to_string()
+    }
+}
+
+/// Payloads of the chosen events a state of the monitor fires, given the latest status.
+fn payloads(events: &[HookEvent], state: &State, status: Option<SystemStatus>) -> Vec<Payload> {
+    let (ip, connection) = match state {
+        State::Approved { ip, connection } => (Some(*ip), *connection),
+        State::Approving(ip) | State::Revoking(ip) => (
+            Some(*ip),
+            status.map(|status| status.connection).unwrap_or_default(),
+        ),
+        _ => (
+            status.map(|status| status.ip),
+            status.map(|status| status.connection).unwrap_or_default(),
+        ),
+    };
+    let (fired, error) = match state {
+        State::Approved { .. } => (vec![HookEvent::State, HookEvent::Approved], None),
+        State::Error { error, .. } => (
+            vec![HookEvent::State, HookEvent::Error],
+            Some(format!("{error:#}")),
+        ),
+        _ => (vec![HookEvent::State], None),
+    };
+    let time = Local::now();
+    fired
+        .into_iter()
+        .filter(|event| events.contains(event))
+        .map(|event| Payload {
+            event,
+            state: state.name(),
+            ip,
+            time_left: connection
+                .is_active()
+                .then(|| connection.time_left.num_seconds()),
+            error: error.clone(),
+            time,
+        })
+        .collect()
+}
+
+struct Hooks {
+    script: Option<PathBuf>,
+    webhook: Option<Url>,
+    client: Client,
+    timeout: Duration,
+}
+
+impl Hooks {
+    async fn run_script(&self, payload: &Payload) -> anyhow::Result<()> {
+        let Some(script) = &self.script else {
+            return Ok(());
+        };
+        // The interface owns the terminal, so the script gets none of it
+        let mut child = Command::new(script)
+            .envs(payload.env())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        time::timeout(self.timeout, child.wait()).await??;
+        Ok(())
+    }
+
+    async fn post_webhook(&self, payload: &Payload) -> anyhow::Result<()> {
+        let Some(webhook) = &self.webhook else {
+            return Ok(());
+        };
+        self.client
+            .post(webhook.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(payload.json())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn run(self: Arc<Self>, payload: Payload) {
+        // Failures are only logged, as the interface owns the terminal and the monitor must not
+        // depend on hooks
+        let (script, webhook) =
+            tokio::join!(self.run_script(&payload), self.post_webhook(&payload));
+        for (hook, result) in [("script", script), ("webhook", webhook)] {
+            match result {
+                Ok(()) => tracing::debug!(hook, event = payload.event.name(), "Hook ran"),
+                Err(err) => tracing::warn!(hook, error = format!("{err:#}"), "Hook failed"),
+            }
+        }
+    }
+}
+
+/// Forwards the states of the monitor to the returned channel, running the hook script and
+/// webhook for the chosen events on tasks of their own so that they never hold up the monitor.
+pub fn tap(
+    args: HookArgs,
+    status_receiver: watch::Receiver<Option<SystemStatus>>,
+    state_receiver: mpsc::Receiver<State>,
+) -> reqwest::Result<mpsc::Receiver<State>> {
+    let timeout = Duration::from_secs(args.hook_timeout);
+    let hooks = Arc::new(Hooks {
+        script: args.hook_script,
+        webhook: args.webhook,
+        client: Client::builder().timeout(timeout).build()?,
+        timeout,
+    });
+    let events = args.hook_on;
+    Ok(monitor_tap::tap(
+        status_receiver,
+        state_receiver,
+        move |state, status| {
+            for payload in payloads(&events, state, status.copied()) {
+                tokio::spawn(Arc::clone(&hooks).run(payload));
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor_tap::test_util::*;
+    use netaccess::Connection;
+    use serde_json::Value;
+    use tokio::sync::oneshot;
+
+    const ALL: [HookEvent; 3] = [HookEvent::State, HookEvent::Approved, HookEvent::Error];
+
+    fn events(payloads: &[Payload]) -> Vec<HookEvent> {
+        payloads.iter().map(|payload| payload.event).collect()
+    }
+
+    #[test]
+    fn approved() {
+        let state = State::Approved {
+            ip: IP.into(),
+            connection: active(60),
+        };
+        let payloads = payloads(&[HookEvent::Approved], &state, None);
+        assert_eq!(events(&payloads), vec![HookEvent::Approved]);
+
+        let json = serde_json::from_str::<Value>(&payloads[0].json()).unwrap();
+        assert_eq!(json["event"], "approved");
+        assert_eq!(json["state"], "approved");
+        assert_eq!(json["ip"], "10.21.0.17");
+        assert_eq!(json["time_left"], 3600);
+        assert_eq!(json["error"], Value::Null);
+    }
+
+    #[test]
+    fn failed_forced_approval() {
+        // The portal already marks the IP address active when approval is forced
+        let status = Some(status(active(60)));
+        let approving = payloads(&ALL, &State::Approving(IP.into()), status);
+        assert_eq!(events(&approving), vec![HookEvent::State]);
+
+        let state = State::Error {
+            error: anyhow::anyhow!("Timed out"),
+            retry_sender: oneshot::channel().0,
+        };
+        let payloads = payloads(&ALL, &state, status);
+        assert_eq!(events(&payloads), vec![HookEvent::State, HookEvent::Error]);
+        let env = payloads[1].env();
+        assert!(env.contains(&("NETACCESS_EVENT", String::from("error"))));
+        assert!(env.contains(&("NETACCESS_STATE", String::from("error"))));
+        assert!(env.contains(&("NETACCESS_IP", String::from("10.21.0.17"))));
+        assert!(env.contains(&("NETACCESS_TIME_LEFT", String::from("3600"))));
+        assert!(env.contains(&("NETACCESS_ERROR", String::from("Timed out"))));
+    }
+
+    #[test]
+    fn inactive() {
+        let status = status(Connection::default());
+        let checking = payloads(&ALL, &State::CheckingStatus, Some(status));
+        assert_eq!(events(&checking), vec![HookEvent::State]);
+        let env = checking[0].env();
+        assert!(env.contains(&("NETACCESS_STATE", String::from("checking-status"))));
+        assert!(env.contains(&("NETACCESS_TIME_LEFT", String::new())));
+        assert!(payloads(&[HookEvent::Approved], &State::CheckingStatus, Some(status)).is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::tls::TlsConfig;
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    ClientBuilder, Proxy, Url,
+};
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Family of IP addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    /// IPv4 addresses.
+    V4,
+    /// IPv6 addresses.
+    V6,
+}
+
+impl IpFamily {
+    /// Family of the given address.
+    pub fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => Self::V4,
+            IpAddr::V6(_) => Self::V6,
+        }
+    }
+
+    /// Whether the address belongs to the family.
+    pub fn matches(self, ip: &IpAddr) -> bool {
+        Self::of(ip) == self
+    }
+}
+
+impl Display for IpFamily {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V4 => "IPv4",
+            Self::V6 => "IPv6",
+        }
+        .fmt(f)
+    }
+}
+
+/// Resolves host names to the addresses of a single family only, so that the portal sees
+/// requests come from an address of that family.
+struct FamilyResolver(IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let addresses = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| family.matches(&address.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addresses.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    format!("{} has no {family} address", name.as_str()),
+                )
+                .into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// How the client connects to the portal.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Duration after which connecting to the portal is abandoned.
+    pub connect_timeout: Duration,
+    /// Duration after which a request is abandoned, including reading the response.
+    pub timeout: Duration,
+    /// Number of times a request is retried after a timeout, a failed connection or a server
+    /// error. Queries and logins are simply sent again, while approvals and revocations are
+    /// only posted again once the status shows the portal did not apply them.
+    pub retries: u32,
+    /// Delay before the first retry, which doubles with every further retry.
+    pub retry_backoff: Duration,
+    /// Proxy to send all requests through, with an `http`, `https`, `socks5` or `socks5h` scheme.
+    /// Proxies of the environment such as `HTTPS_PROXY` are used if none is given.
+    pub proxy: Option<Url>,
+    /// User agent sent with requests instead of the one of reqwest.
+    pub user_agent: Option<String>,
+    /// Local address to connect from, which is also the IP address that gets approved.
+    pub local_address: Option<IpAddr>,
+    /// Family of addresses to reach the portal over, which is the family of the IP address that
+    /// gets approved. If none is given, either may be used and the system IP address is the
+    /// address of a network interface found in the connection table, defaulting to IPv4.
+    pub family: Option<IpFamily>,
+    /// How the certificate of the portal is verified.
+    pub tls: TlsConfig,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            retry_backoff: Duration::from_secs(1),
+            proxy: None,
+            user_agent: None,
+            local_address: None,
+            family: None,
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Family of addresses the portal is reached over, either as configured or as implied by
+    /// the local address.
+    pub(crate) fn effective_family(&self) -> Option<IpFamily> {
+        self.family
+            .or(self.local_address.as_ref().map(IpFamily::of))
+    }
+
+    /// Applies everything but TLS to the builder, which [`TlsConfig`] configures separately.
+    pub(crate) fn apply(&self, mut builder: ClientBuilder) -> reqwest::Result<ClientBuilder> {
+        builder = builder
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout)
+            .local_address(self.local_address);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.clone())?);
+        }
+        if let Some(family) = self.effective_family() {
+            builder = builder.dns_resolver(Arc::new(FamilyResolver(family)));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resolves_single_family() {
+        let addresses = FamilyResolver(IpFamily::V4)
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert!(!addresses.is_empty());
+        assert!(addresses.iter().all(SocketAddr::is_ipv4));
+    }
+}
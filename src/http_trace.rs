@@ -0,0 +1,201 @@
+use chrono::Local;
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION, COOKIE, SET_COOKIE},
+    Request, Response, Url,
+};
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Placeholder written in place of credentials and session cookies.
+const REDACTED: &str = "<redacted>";
+
+/// Form fields whose values are never written to the trace.
+const SECRET_FIELDS: [&str; 1] = ["userPassword"];
+
+/// Number of bytes of a response body written to the trace.
+const BODY_EXCERPT_LEN: usize = 4096;
+
+/// Identifies the trace entry of a response, so that its body can be appended once read.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EntryId(usize);
+
+/// Record of every request sent to the portal and the response it got, including the redirects
+/// it followed, appended to a file to attach to bug reports when the portal misbehaves.
+///
+/// Passwords in submitted forms, the `Authorization` header and the values of cookies are
+/// redacted before anything is written.
+#[derive(Debug)]
+pub struct HttpTrace {
+    file: Mutex<File>,
+    count: AtomicUsize,
+}
+
+impl HttpTrace {
+    /// Appends the trace to the given file, creating it if needed.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+            count: AtomicUsize::default(),
+        })
+    }
+
+    fn write(&self, text: &str) {
+        // Tracing must never fail the operation it observes
+        let mut file = self.file.lock().expect("HTTP trace lock poisoned");
+        if let Err(err) = file.write_all(text.as_bytes()) {
+            tracing::warn!(error = %err, "Failed to write HTTP trace");
+        }
+    }
+
+    /// Records a request along with the cookies the session sends with it and its outcome.
+    /// Redirects are listed in the order they were followed, starting at the request URL.
+    pub(crate) fn record(
+        &self,
+        request: &Request,
+        cookies: Option<HeaderValue>,
+        redirects: &[Url],
+        result: &reqwest::Result<Response>,
+        elapsed: Duration,
+    ) -> EntryId {
+        let id = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut text = format!(
+            "=== #{id} {} {} {}\n",
+            Local::now().to_rfc3339(),
+            request.method(),
+            request.url()
+        );
+        let headers = request
+            .headers()
+            .iter()
+            .chain(cookies.iter().map(|cookies| (&COOKIE, cookies)));
+        for (name, value) in headers {
+            let _ = writeln!(text, "> {name}: {}", redact_header(name, value));
+        }
+        if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+            let _ = writeln!(
+                text,
+                "> body: {}",
+                redact_form(&String::from_utf8_lossy(body))
+            );
+        }
+
+        match result {
+            Ok(response) => {
+                let _ = writeln!(
+                    text,
+                    "< {} in {} ms",
+                    response.status(),
+                    elapsed.as_millis()
+                );
+                if redirects.len() > 1 {
+                    let chain = redirects.iter().map(Url::as_str).collect::<Vec<_>>();
+                    let _ = writeln!(text, "< redirects: {}", chain.join(" -> "));
+                }
+                for (name, value) in response.headers() {
+                    let _ = writeln!(text, "< {name}: {}", redact_header(name, value));
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(text, "< failed after {} ms: {err}", elapsed.as_millis());
+            }
+        }
+        self.write(&text);
+        EntryId(id)
+    }
+
+    /// Appends the beginning of the body of a recorded response.
+    pub(crate) fn record_body(&self, EntryId(id): EntryId, body: &str) {
+        let mut end = body.len().min(BODY_EXCERPT_LEN);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.write(&format!(
+            "--- #{id} body, {} of {} bytes\n{}\n",
+            end,
+            body.len(),
+            &body[..end]
+        ));
+    }
+}
+
+fn redact_header(name: &HeaderName, value: &HeaderValue) -> String {
+    let value = String::from_utf8_lossy(value.as_bytes());
+    match name {
+        name if name == AUTHORIZATION => String::from(REDACTED),
+        name if name == COOKIE => value
+            .split(';')
+            .map(|cookie| redact_pair(cookie.trim()))
+            .collect::<Vec<_>>()
+            .join("; "),
+        // Only the value of a cookie is secret, its attributes tell how the session is scoped
+        name if name == SET_COOKIE => match value.split_once(';') {
+            Some((cookie, attributes)) => format!("{};{attributes}", redact_pair(cookie.trim())),
+            None => redact_pair(value.trim()),
+        },
+        _ => value.into_owned(),
+    }
+}
+
+fn redact_pair(pair: &str) -> String {
+    match pair.split_once('=') {
+        Some((name, _)) => format!("{name}={REDACTED}"),
+        None => pair.to_owned(),
+    }
+}
+
+/// Redacts the values of secret fields in a URL encoded form.
+fn redact_form(form: &str) -> String {
+    form.split('&')
+        .map(|field| match field.split_once('=') {
+            Some((name, _)) if SECRET_FIELDS.contains(&name) => format!("{name}={REDACTED}"),
+            _ => field.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::LOCATION;
+
+    #[test]
+    fn redaction() {
+        assert_eq!(
+            redact_form("userLogin=ab12c345&userPassword=hunter%262"),
+            "userLogin=ab12c345&userPassword=<redacted>"
+        );
+        assert_eq!(
+            redact_form("duration=2&approveBtn="),
+            "duration=2&approveBtn="
+        );
+        assert_eq!(
+            redact_header(&COOKIE, &HeaderValue::from_static("PHPSESSID=abc; lang=en")),
+            "PHPSESSID=<redacted>; lang=<redacted>"
+        );
+        assert_eq!(
+            redact_header(
+                &SET_COOKIE,
+                &HeaderValue::from_static("PHPSESSID=abc; path=/; HttpOnly")
+            ),
+            "PHPSESSID=<redacted>; path=/; HttpOnly"
+        );
+        assert_eq!(
+            redact_header(&AUTHORIZATION, &HeaderValue::from_static("Basic YWI6Y2Q=")),
+            "<redacted>"
+        );
+        assert_eq!(
+            redact_header(&LOCATION, &HeaderValue::from_static("/account/index")),
+            "/account/index"
+        );
+    }
+}
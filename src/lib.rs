@@ -0,0 +1,52 @@
+//! Client for <https://netaccess.iitm.ac.in>, the portal managing internet access at IIT Madras.
+//!
+//! [`AccountManager`] logs in with the [`User`] credentials and queries, approves or revokes the
+//! authorization of IP addresses, while [`Monitor`] keeps the system IP address approved in the
+//! background, optionally only during the windows of a [`Schedule`]. [`Probe`] verifies that the
+//! internet is actually reachable once approved.
+//!
+//! ```no_run
+//! use netaccess::{AccountManager, User};
+//!
+//! # async fn run() -> Result<(), netaccess::Error> {
+//! let account_manager = AccountManager::new()?;
+//! let user = User::new("XX19X001".to_owned(), "password".to_owned());
+//! account_manager.check_user_password(&user).await?;
+//! let status = account_manager.status(&user).await?;
+//! println!("{} is active: {}", status.system_status.ip, status.system_status.connection.is_active());
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+mod account_manager;
+mod connection_table;
+mod connectivity;
+mod duration_format;
+mod http_config;
+mod http_trace;
+mod metrics;
+mod monitor;
+mod native_backend;
+mod portal_client;
+mod schedule;
+#[cfg(test)]
+mod test_util;
+mod tls;
+mod user;
+
+pub use account_manager::{
+    AccountManager, Connection, DurationOption, Error, Outcome, Report, Status, SystemStatus,
+};
+pub use connection_table::LayoutError;
+pub use connectivity::{Probe, Unreachable};
+pub use duration_format::{DurationStyle, UnknownDurationStyle};
+pub use http_config::{HttpConfig, IpFamily};
+pub use http_trace::HttpTrace;
+pub use metrics::Metrics;
+pub use monitor::{Mismatch, Monitor, MonitorConfig, State};
+pub use portal_client::PortalClient;
+pub use schedule::{Action, Phase, Schedule, ScheduleError, Window};
+pub use tls::{CertificateInfo, Pin, PinError, TlsConfig, TlsError, TlsReport};
+pub use user::User;
@@ -1,29 +1,143 @@
-mod account_manager;
-mod monitor;
+mod exit_code;
+mod hooks;
+mod metrics_server;
+mod monitor_tap;
 mod monitor_ui;
+mod notifications;
 #[cfg(target_family = "unix")]
 mod openssl_conf;
-mod user;
+mod status_view;
 
-use account_manager::{AccountManager, SystemStatus};
 use anyhow::{bail, Context};
-use clap::{Parser, Subcommand, ValueEnum};
-use monitor::Monitor;
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    value_parser, ArgAction, Parser, Subcommand,
+};
+use exit_code::ExitCode;
+use hooks::HookArgs;
+use netaccess::{
+    AccountManager, DurationStyle, HttpConfig, HttpTrace, IpFamily, Monitor, MonitorConfig,
+    Outcome, Pin, Probe, Report, Schedule, TlsConfig, User, Window,
+};
+use notifications::NotifyEvent;
+use reqwest::Url;
 use std::{
-    fmt::{self, Display, Formatter},
-    io::{self, Write},
-    sync::Arc,
+    fs::OpenOptions,
+    io::{self, IsTerminal, Write},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    process,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use tokio::sync::{mpsc, watch};
 use tokio_util::sync::CancellationToken;
-use user::User;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
 
 const MIN_SUSPEND_DURATION: u64 = 30;
+const MIN_WATCH_INTERVAL: u64 = 10;
 const MSG_CHANNEL_BUF_SIZE: usize = 20;
 
 #[derive(Debug, Parser)]
+#[command(after_help = ExitCode::help())]
 struct Cli {
+    /// Do not print anything and only communicate the outcome through the exit code. The monitor
+    /// still renders its interface
+    #[arg(short, long, global = true, default_value_t = false)]
+    quiet: bool,
+
+    /// Style used to display durations such as the time left of a connection
+    #[arg(long, global = true, default_value_t = DurationStyle::Long, value_parser = duration_style_parser())]
+    duration_style: DurationStyle,
+
+    /// Write every portal page fetched to this directory, useful for reporting portal changes.
+    /// The pages contain your connection details, so review them before sharing
+    #[arg(long, global = true, value_name = "DIR")]
+    dump_html: Option<PathBuf>,
+
+    /// Append every portal request and response, including the redirects followed, headers and
+    /// the beginning of the body, to this file. Passwords and session cookies are redacted, but
+    /// pages contain your connection details, so review the file before sharing
+    #[arg(long, global = true, value_name = "FILE")]
+    trace_http: Option<PathBuf>,
+
+    /// Log portal requests and monitor activity, repeat for more detail
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Append logs to this file instead of printing them. The monitor and status --watch only log to
+    /// a file, as they redraw the terminal
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+
+    /// The duration of time in seconds after which connecting to the portal is abandoned
+    #[arg(
+        long,
+        global = true,
+        env = "NETACCESS_CONNECT_TIMEOUT",
+        default_value_t = 5
+    )]
+    connect_timeout: u64,
+
+    /// The duration of time in seconds after which a portal request is abandoned, including
+    /// reading the response. Raise it on congested networks
+    #[arg(
+        long,
+        global = true,
+        env = "NETACCESS_REQUEST_TIMEOUT",
+        default_value_t = 5
+    )]
+    request_timeout: u64,
+
+    /// Number of times a portal request is retried after a timeout, a failed connection or a
+    /// server error, waiting a second before the first retry and twice as long every time after.
+    /// Approvals and revocations are only posted again if the portal did not apply them
+    #[arg(long, global = true, env = "NETACCESS_RETRIES", default_value_t = 2)]
+    retries: u32,
+
+    /// Proxy to send portal requests through, such as http://proxy:3128 or
+    /// socks5h://localhost:1080. Defaults to the proxies set by HTTP_PROXY and HTTPS_PROXY
+    #[arg(long, global = true, env = "NETACCESS_PROXY", value_name = "URL")]
+    proxy: Option<Url>,
+
+    /// User agent to send with portal requests
+    #[arg(long, global = true, env = "NETACCESS_USER_AGENT")]
+    user_agent: Option<String>,
+
+    /// Local address to send portal requests from, which is also the IP address approved,
+    /// revoked and reported as the system IP address
+    #[arg(
+        long,
+        global = true,
+        env = "NETACCESS_LOCAL_ADDRESS",
+        value_name = "IP"
+    )]
+    local_address: Option<IpAddr>,
+
+    /// Reach the portal over IPv4 only, approving and revoking the IPv4 address of the system
+    #[arg(short = '4', long, global = true, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Reach the portal over IPv6 only, approving and revoking the IPv6 address of the system
+    #[arg(short = '6', long, global = true)]
+    ipv6: bool,
+
+    /// PEM file of extra root certificates to trust for the portal, such as the CA of an
+    /// intercepting proxy. Repeat to add several
+    #[arg(long = "ca-bundle", global = true, value_name = "FILE")]
+    ca_bundles: Vec<PathBuf>,
+
+    /// SHA-256 fingerprint of the portal certificate, printed as certificate 0 by tls-check, or
+    /// of its public key as sha256//<base64>. The portal certificate must match one of the pins,
+    /// otherwise connections fail before credentials are sent. Pinned connections are made with
+    /// rustls, which needs the portal to offer ECDHE. Repeat to allow several
+    #[arg(long = "pin", global = true, value_name = "PIN")]
+    pins: Vec<Pin>,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -31,17 +145,52 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Query the status of a user account
-    Status,
+    Status {
+        /// Refresh the status every this many seconds, 30 if not given, until interrupted,
+        /// highlighting connections that appeared, expired or got activated since the previous
+        /// refresh
+        #[arg(
+            short,
+            long,
+            value_name = "SECS",
+            num_args = 0..=1,
+            default_missing_value = "30",
+            value_parser = value_parser!(u64).range(MIN_WATCH_INTERVAL..)
+        )]
+        watch: Option<u64>,
+    },
+    /// List the approval durations offered by the portal
+    Durations,
     /// Approve system IP address for a particular duration
     Approve {
-        /// The duration for which an IP address should be approved for
-        #[arg(short, long, default_value_t = ApproveDuration::Hour, value_enum)]
-        duration: ApproveDuration,
+        /// The duration for which an IP address should be approved for, as listed by the
+        /// durations command or one of hour, day and month. Defaults to the duration preselected
+        /// by the portal
+        #[arg(short, long)]
+        duration: Option<String>,
 
         /// Forcefully attempt to approve even if system IP is marked as active
         #[arg(short, long, default_value_t = false)]
         force: bool,
     },
+    /// Approve system IP address unless already active, then wait until the internet is reachable
+    Ensure {
+        /// The duration for which an IP address should be approved for, as listed by the
+        /// durations command or one of hour, day and month. Defaults to the duration preselected
+        /// by the portal
+        #[arg(short, long)]
+        duration: Option<String>,
+
+        /// URL probed with a HEAD request to check whether the internet is reachable, which must
+        /// respond successfully without redirecting. Repeat to probe several, any of which is
+        /// enough
+        #[arg(long = "probe-url", value_name = "URL", default_value = Probe::DEFAULT_TARGET)]
+        probe_urls: Vec<Url>,
+
+        /// The duration of time in seconds to wait for the internet to become reachable
+        #[arg(short, long, default_value_t = 60)]
+        timeout: u64,
+    },
     /// Revoke authorization of an IP address
     Revoke {
         /// The IP address for which access should be revoked. Do not specify this flag to revoke
@@ -49,144 +198,463 @@ enum Command {
         #[arg(short, long)]
         ip: Option<String>,
     },
+    /// Print the TLS protocol, cipher suite and certificate chain negotiated with the portal,
+    /// and whether the chain passes verification. Connects directly, even if a proxy is set
+    TlsCheck,
+    /// Remove OpenSSL config files left in the cache directory by earlier versions
+    Cleanup,
     /// Periodically monitor the status of system IP address and approve if access is revoked
     Monitor {
         /// The duration of time in seconds to sleep before waking up to check status
-        #[arg(short, long, default_value_t = 5 * 60)]
+        #[arg(
+            short,
+            long,
+            default_value_t = 5 * 60,
+            value_parser = value_parser!(u64).range(MIN_SUSPEND_DURATION..)
+        )]
         suspend_duration: u64,
 
-        /// The duration for which an IP address should be approved for
-        #[arg(short, long, default_value_t = ApproveDuration::Hour, value_enum)]
-        approve_duration: ApproveDuration,
+        /// The duration for which an IP address should be approved for, as listed by the
+        /// durations command or one of hour, day and month. Defaults to the duration preselected
+        /// by the portal
+        #[arg(short, long)]
+        approve_duration: Option<String>,
+
+        /// Window of the week in which to keep the IP address approved, let it lapse or revoke
+        /// it, written as "[keep|lapse|revoke] <days> [HH:MM-HH:MM]" such as "mon-fri 09:00-18:00"
+        /// or "revoke sat,sun". Repeat to add windows, the first matching window wins. Outside of
+        /// all windows the IP address lapses if any window keeps it approved
+        #[arg(long = "schedule", value_name = "WINDOW")]
+        schedule: Vec<Window>,
+
+        /// Probe whether the internet is reachable on every check, and report when it disagrees
+        /// with the connection state shown by the portal
+        #[arg(long, default_value_t = false)]
+        probe: bool,
+
+        /// URL probed with a HEAD request to check whether the internet is reachable, which must
+        /// respond successfully without redirecting. Repeat to probe several, any of which is
+        /// enough
+        #[arg(long = "probe-url", value_name = "URL", default_value = Probe::DEFAULT_TARGET)]
+        probe_urls: Vec<Url>,
+
+        /// Forcefully approve again once if the portal marks the system IP as active but the
+        /// internet is unreachable
+        #[arg(long, default_value_t = false, requires = "probe")]
+        reapprove_unreachable: bool,
+
+        /// Monitor events to raise desktop notifications for, through the freedesktop
+        /// notification service
+        #[arg(long, value_name = "EVENT", value_delimiter = ',')]
+        notify: Vec<NotifyEvent>,
+
+        #[command(flatten)]
+        hooks: Box<HookArgs>,
+
+        /// Address to serve Prometheus metrics of the portal operations on at /metrics, such as
+        /// 127.0.0.1:9898
+        #[arg(long, value_name = "ADDR")]
+        metrics_addr: Option<SocketAddr>,
     },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
-enum ApproveDuration {
-    Hour,
-    Day,
-    Month,
+fn duration_style_parser() -> impl TypedValueParser<Value = DurationStyle> {
+    PossibleValuesParser::new(DurationStyle::ALL.map(DurationStyle::name))
+        .map(|style| style.parse().expect("Only possible values are parsed"))
 }
 
-impl Display for ApproveDuration {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        self.to_possible_value()
-            .expect("No values are skipped")
-            .get_name()
-            .fmt(f)
+macro_rules! report {
+    ( $quiet:expr, $($arg:tt)* ) => {
+        if !$quiet {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[tokio::main]
+async fn main() -> process::ExitCode {
+    let cli = Cli::parse();
+    let quiet = cli.quiet;
+    match run(cli).await {
+        Ok(code) => code,
+        Err(err) => {
+            if !quiet {
+                eprintln!("Error: {err:?}");
+                if err
+                    .downcast_ref::<netaccess::Error>()
+                    .is_some_and(netaccess::Error::is_tls_incompatible)
+                {
+                    eprintln!(
+                        "\nThe portal offers none of the TLS 1.2 or 1.3 cipher suites \
+                         supported. Please report the output of `netaccess tls-check` and \
+                         `openssl s_client -connect netaccess.iitm.ac.in:443 -cipher \
+                         DEFAULT:@SECLEVEL=1 </dev/null`"
+                    );
+                }
+            }
+            ExitCode::from(&err)
+        }
+    }
+    .into()
+}
+
+fn read_user(quiet: bool) -> anyhow::Result<User> {
+    // Prompts go to stderr so that they never end up in the output of a piped command
+    let terminal = io::stdin().is_terminal();
+    if terminal && !quiet {
+        eprint!("Enter username: ");
+        io::stderr().flush()?;
+    }
+    // user names are expected to be of the format XX19X001
+    let mut buf = String::with_capacity(8);
+    io::stdin()
+        .read_line(&mut buf)
+        .context("Failed to read username")?;
+    let user = buf.trim();
+    // Without a terminal, as in network-up hooks and systemd units, the password is on the line
+    // after the username
+    let password = if !terminal {
+        io::stdin()
+            .lines()
+            .next()
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+    } else if quiet {
+        rpassword::read_password()
+    } else {
+        rpassword::prompt_password(format!("Enter password for {user}: "))
     }
+    .context("Failed to read password")?;
+    Ok(User::new(user.to_owned(), password))
 }
 
-impl From<ApproveDuration> for usize {
-    fn from(val: ApproveDuration) -> Self {
-        match val {
-            ApproveDuration::Hour => 1,
-            ApproveDuration::Day => 2,
-            ApproveDuration::Month => 3,
+fn init_logging(cli: &Cli) -> anyhow::Result<()> {
+    let level = match cli.verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    // Dependencies are only as verbose as the application at the highest verbosity
+    let filter = Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+        .with_default(if cli.verbose > 2 {
+            level
+        } else {
+            LevelFilter::WARN
+        });
+    let subscriber = tracing_subscriber::fmt().with_max_level(LevelFilter::TRACE);
+    match &cli.log_file {
+        Some(log_file) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .with_context(|| format!("Failed to open log file {}", log_file.display()))?;
+            subscriber
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .finish()
+                .with(filter)
+                .init();
+        }
+        None if !cli.quiet
+            && !matches!(
+                cli.command,
+                Command::Monitor { .. } | Command::Status { watch: Some(_) }
+            ) =>
+        {
+            subscriber
+                .with_writer(io::stderr)
+                .finish()
+                .with(filter)
+                .init();
         }
+        None => {}
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    #[cfg(target_family = "unix")]
-    let _cnf = openssl_conf::OpenSSLConf::new()?;
+async fn run(cli: Cli) -> anyhow::Result<ExitCode> {
+    init_logging(&cli)?;
 
-    let cli = Cli::parse();
-    let account_manager = Arc::new(AccountManager::new()?);
+    let quiet = cli.quiet;
+    if let Command::Cleanup = cli.command {
+        #[cfg(target_family = "unix")]
+        for path in openssl_conf::remove_leaked()? {
+            report!(quiet, "Removed {}", path.display());
+        }
+        return Ok(ExitCode::Success);
+    }
 
-    let user = {
-        print!("Enter username: ");
-        io::stdout().flush()?;
-        // user names are expected to be of the format XX19X001
-        let mut buf = String::with_capacity(8);
-        io::stdin()
-            .read_line(&mut buf)
-            .context("Failed to read username")?;
-        let user = buf.trim();
-        let password = rpassword::prompt_password(format!("Enter password for {user}: "))
-            .context("Failed to read password")?;
-        anyhow::Ok(User::new(user.to_owned(), password))
-    }?;
-
-    account_manager.check_user_passowrd(&user).await?;
-
-    match cli.command {
-        Command::Status => display_status(&account_manager, &user).await?,
+    let duration_style = cli.duration_style;
+    let account_manager = AccountManager::with_config(HttpConfig {
+        connect_timeout: Duration::from_secs(cli.connect_timeout),
+        timeout: Duration::from_secs(cli.request_timeout),
+        retries: cli.retries,
+        retry_backoff: HttpConfig::default().retry_backoff,
+        proxy: cli.proxy,
+        user_agent: cli.user_agent,
+        local_address: cli.local_address,
+        family: match (cli.ipv4, cli.ipv6) {
+            (true, _) => Some(IpFamily::V4),
+            (_, true) => Some(IpFamily::V6),
+            _ => None,
+        },
+        tls: TlsConfig {
+            ca_bundles: cli.ca_bundles,
+            pins: cli.pins,
+        },
+    })?;
+    let account_manager = match cli.dump_html {
+        Some(dump_dir) => account_manager.with_dump_dir(dump_dir),
+        None => account_manager,
+    };
+    let account_manager = Arc::new(match &cli.trace_http {
+        Some(trace_file) => {
+            account_manager.with_http_trace(HttpTrace::create(trace_file).with_context(|| {
+                format!("Failed to open HTTP trace file {}", trace_file.display())
+            })?)
+        }
+        None => account_manager,
+    });
+
+    if let Command::TlsCheck = cli.command {
+        return check_tls(&account_manager, quiet).await;
+    }
+
+    let user = read_user(quiet)?;
+
+    account_manager.check_user_password(&user).await?;
+
+    let outcome = match cli.command {
+        Command::Status { watch: None } => {
+            let status = account_manager.status(&user).await?;
+            if !quiet {
+                status_view::print(&status, duration_style);
+                display_alerts(quiet, status.alerts());
+            }
+            None
+        }
+        Command::Status {
+            watch: Some(interval),
+        } => {
+            status_view::watch(
+                &account_manager,
+                &user,
+                Duration::from_secs(interval),
+                duration_style,
+                quiet,
+            )
+            .await?;
+            None
+        }
+        Command::Durations => {
+            let status = account_manager.status(&user).await?;
+            for duration in status.durations() {
+                report!(
+                    quiet,
+                    "{}{}",
+                    duration.label,
+                    if duration.is_default {
+                        " (default)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            None
+        }
         Command::Approve { duration, force } => {
-            let ip = account_manager
-                .approve(&user, duration.into(), force)
+            let Report {
+                ip,
+                outcome,
+                alerts,
+            } = account_manager
+                .approve(&user, duration.as_deref(), force)
                 .await?;
-            println!("Approved {ip} for {user} for 1 {duration} successfully");
+            match outcome {
+                Outcome::AlreadyActive { time_left } => report!(
+                    quiet,
+                    "{ip} is already active for {}, use --force to approve again",
+                    duration_style.format(&time_left)
+                ),
+                Outcome::Approved { connection } => report!(
+                    quiet,
+                    "Approved {ip} for {user} successfully, active for {}",
+                    duration_style.format(&connection.time_left)
+                ),
+                _ => {}
+            }
+            display_alerts(quiet, &alerts);
+            Some(outcome)
+        }
+        Command::Ensure {
+            duration,
+            probe_urls,
+            timeout,
+        } => {
+            let probe = Probe::new(probe_urls)?;
+            let Report {
+                ip,
+                outcome,
+                alerts,
+            } = account_manager
+                .approve(&user, duration.as_deref(), false)
+                .await?;
+            match outcome {
+                Outcome::AlreadyActive { time_left } => report!(
+                    quiet,
+                    "{ip} is already active for {}",
+                    duration_style.format(&time_left)
+                ),
+                Outcome::Approved { connection } => report!(
+                    quiet,
+                    "Approved {ip} for {user} successfully, active for {}",
+                    duration_style.format(&connection.time_left)
+                ),
+                _ => {}
+            }
+            display_alerts(quiet, &alerts);
+            let target = probe.wait(Duration::from_secs(timeout)).await?;
+            report!(quiet, "Internet is reachable, {target} responded");
+            None
         }
         Command::Revoke { ip } => {
-            let ip = account_manager.revoke(&user, ip).await?;
-            println!("Revoked {ip} for {user} successfully");
+            let Report {
+                ip,
+                outcome,
+                alerts,
+            } = account_manager.revoke(&user, ip).await?;
+            match outcome {
+                Outcome::NotRegistered => {
+                    report!(
+                        quiet,
+                        "{ip} is not registered for {user}, nothing to revoke"
+                    )
+                }
+                Outcome::AlreadyInactive => {
+                    report!(quiet, "{ip} is already inactive, nothing to revoke")
+                }
+                _ => report!(quiet, "Revoked {ip} for {user} successfully"),
+            }
+            display_alerts(quiet, &alerts);
+            Some(outcome)
         }
+        Command::TlsCheck | Command::Cleanup => unreachable!("Runs without logging in"),
         Command::Monitor {
             suspend_duration,
             approve_duration,
+            schedule,
+            probe,
+            probe_urls,
+            reapprove_unreachable,
+            notify,
+            hooks,
+            metrics_addr,
         } => {
-            if suspend_duration < MIN_SUSPEND_DURATION {
-                bail!("Suspend duration is less than minimum allowed {MIN_SUSPEND_DURATION}");
+            if let Some(approve_duration) = &approve_duration {
+                // Fail now rather than on the first approval, which may be hours away
+                account_manager
+                    .status(&user)
+                    .await?
+                    .duration(Some(approve_duration))?;
+            }
+            let metrics_handle = match metrics_addr {
+                Some(addr) => Some(
+                    metrics_server::serve(addr, Arc::clone(&account_manager))
+                        .await
+                        .with_context(|| format!("Failed to serve metrics on {addr}"))?,
+                ),
+                None => None,
+            };
+            if !notify.is_empty() && !NotifyEvent::is_supported() {
+                bail!("Desktop notifications are not supported on this platform");
             }
 
+            let config = MonitorConfig {
+                approve_duration,
+                suspend_duration: Duration::from_secs(suspend_duration),
+                schedule: Schedule::new(schedule),
+                probe: probe.then(|| Probe::new(probe_urls)).transpose()?,
+                reapprove_unreachable,
+            };
             let mut monitor = Monitor::new(&account_manager);
             let (status_sender, status_receiver) = watch::channel(None);
             let (state_sender, state_receiver) = mpsc::channel(MSG_CHANNEL_BUF_SIZE);
+            let state_receiver = if notify.is_empty() {
+                state_receiver
+            } else {
+                notifications::tap(
+                    notify,
+                    duration_style,
+                    status_receiver.clone(),
+                    state_receiver,
+                )
+            };
+            let state_receiver = if hooks.is_empty() {
+                state_receiver
+            } else {
+                hooks::tap(*hooks, status_receiver.clone(), state_receiver)?
+            };
 
             let cancellation_token = CancellationToken::new();
             let cancellation_token_child = cancellation_token.child_token();
 
-            let ui_handle = monitor_ui::run(status_receiver, state_receiver, cancellation_token);
-            monitor.start(
-                user,
-                approve_duration.into(),
-                Duration::from_secs(suspend_duration),
-                status_sender,
-                state_sender,
+            let ui_handle = monitor_ui::run(
+                status_receiver,
+                state_receiver,
+                cancellation_token,
+                duration_style,
             );
+            monitor.start(user, config, status_sender, state_sender);
             cancellation_token_child.cancelled().await;
             monitor.stop();
+            if let Some(metrics_handle) = metrics_handle {
+                metrics_handle.abort();
+            }
             ui_handle.await??;
+            None
         }
-    }
+    };
 
-    Ok(())
+    Ok(outcome.map_or(ExitCode::Success, ExitCode::from))
 }
 
-async fn display_status(account_manager: &AccountManager, user: &User) -> anyhow::Result<()> {
-    let status = account_manager.status(user).await?;
-    let SystemStatus { ip, connection } = status.system_status;
-    println!(
-        "Your IP address is {ip} and {}",
-        if connection.is_active() {
-            format!(
-                "active for {}",
-                monitor_ui::format_duration(&connection.time_left)
-            )
-        } else {
-            String::from("inactive")
-        }
+async fn check_tls(account_manager: &AccountManager, quiet: bool) -> anyhow::Result<ExitCode> {
+    let report = account_manager.check_tls().await?;
+    // The TLS libraries of Windows and macOS do not report what was negotiated
+    let unreported = "not reported on this platform";
+    report!(
+        quiet,
+        "Protocol: {}",
+        report.protocol.as_deref().unwrap_or(unreported)
     );
-    let connections = status.connections();
-    println!(
-        "Number of other registered connections: {}",
-        connections.len()
+    report!(
+        quiet,
+        "Cipher suite: {}",
+        report.cipher_suite.as_deref().unwrap_or(unreported)
     );
-    if !connections.is_empty() {
-        println!("S.No.\tIP\t\tTime left");
+    for (index, certificate) in report.chain.iter().enumerate() {
+        report!(quiet, "Certificate {index}: {}", certificate.subject);
+        report!(quiet, "  Issuer: {}", certificate.issuer);
+        report!(quiet, "  Valid until: {}", certificate.not_after);
+        report!(quiet, "  Fingerprint: {}", certificate.fingerprint);
+        report!(quiet, "  Public key: {}", certificate.public_key);
     }
-    for (index, (ip, connection)) in connections.iter().enumerate() {
-        println!(
-            "{}\t{ip}\t{}",
-            index + 1,
-            if connection.is_active() {
-                monitor_ui::format_duration(&connection.time_left)
-            } else {
-                String::from("Inactive or expired")
-            }
-        );
+    Ok(match report.verification_error {
+        None => {
+            report!(quiet, "Verification: passed");
+            ExitCode::Success
+        }
+        Some(error) => {
+            report!(quiet, "Verification: failed, {error}");
+            ExitCode::CertificateRejected
+        }
+    })
+}
+
+fn display_alerts(quiet: bool, alerts: &[String]) {
+    for alert in alerts {
+        report!(quiet, "Portal says: {alert}");
     }
-    Ok(())
 }
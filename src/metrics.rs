@@ -0,0 +1,246 @@
+use crate::account_manager::{Error, Status};
+use chrono::{DateTime, Utc};
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Portal operations that are counted and timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Operation {
+    Login,
+    Status,
+    Approve,
+    Revoke,
+}
+
+impl Operation {
+    const ALL: [Self; 4] = [Self::Login, Self::Status, Self::Approve, Self::Revoke];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Status => "status",
+            Self::Approve => "approve",
+            Self::Revoke => "revoke",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Histogram {
+    /// Number of observations in each bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| value <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    successes: BTreeMap<Operation, u64>,
+    failures: BTreeMap<Operation, u64>,
+    latencies: BTreeMap<Operation, Histogram>,
+    errors: BTreeMap<&'static str, u64>,
+    time_left: Option<chrono::Duration>,
+    connections: Option<usize>,
+    last_success: Option<DateTime<Utc>>,
+}
+
+/// Counts, latencies and errors of portal operations along with the last known status of the
+/// account, recorded by [`AccountManager`](crate::AccountManager) and rendered in the Prometheus
+/// text exposition format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("Metrics lock poisoned")
+    }
+
+    pub(crate) fn record(&self, operation: Operation, elapsed: Duration, error: Option<&Error>) {
+        let mut inner = self.inner();
+        let results = match error {
+            Some(error) => {
+                *inner.errors.entry(error.category()).or_default() += 1;
+                &mut inner.failures
+            }
+            None => &mut inner.successes,
+        };
+        *results.entry(operation).or_default() += 1;
+        inner
+            .latencies
+            .entry(operation)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn record_status(&self, status: &Status) {
+        let mut inner = self.inner();
+        let connection = status.system_status.connection;
+        inner.time_left = Some(if connection.is_active() {
+            connection.time_left
+        } else {
+            chrono::Duration::zero()
+        });
+        inner.connections = Some(status.connections.len() + usize::from(status.system_registered));
+        inner.last_success = Some(Utc::now());
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format. Gauges of the account
+    /// status are left out until the status has been queried successfully.
+    pub fn render(&self) -> String {
+        let inner = self.inner();
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            let _ = writeln!(text, "# HELP netaccess_{name} {help}");
+            let _ = writeln!(text, "# TYPE netaccess_{name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(text, "netaccess_{name}{labels} {value}");
+            }
+        };
+
+        let count = |results: &BTreeMap<Operation, u64>, operation| {
+            results.get(&operation).copied().unwrap_or_default()
+        };
+        metric(
+            "requests_total",
+            "counter",
+            "Portal operations by result.",
+            Operation::ALL
+                .into_iter()
+                .flat_map(|operation| {
+                    [
+                        ("success", count(&inner.successes, operation)),
+                        ("error", count(&inner.failures, operation)),
+                    ]
+                    .map(|(result, value)| {
+                        (
+                            format!("{{operation=\"{}\",result=\"{result}\"}}", operation.name()),
+                            value.to_string(),
+                        )
+                    })
+                })
+                .collect(),
+        );
+
+        let mut samples = vec![];
+        for operation in Operation::ALL {
+            let histogram = inner.latencies.get(&operation).copied().unwrap_or_default();
+            let name = operation.name();
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                samples.push((
+                    format!("_bucket{{operation=\"{name}\",le=\"{bound}\"}}"),
+                    cumulative.to_string(),
+                ));
+            }
+            samples.push((
+                format!("_bucket{{operation=\"{name}\",le=\"+Inf\"}}"),
+                histogram.count.to_string(),
+            ));
+            samples.push((
+                format!("_sum{{operation=\"{name}\"}}"),
+                histogram.sum.to_string(),
+            ));
+            samples.push((
+                format!("_count{{operation=\"{name}\"}}"),
+                histogram.count.to_string(),
+            ));
+        }
+        metric(
+            "request_duration_seconds",
+            "histogram",
+            "Latency of portal operations.",
+            samples,
+        );
+
+        metric(
+            "errors_total",
+            "counter",
+            "Failed portal operations by error category.",
+            inner
+                .errors
+                .iter()
+                .map(|(category, count)| {
+                    (format!("{{category=\"{category}\"}}"), count.to_string())
+                })
+                .collect(),
+        );
+
+        if let Some(time_left) = inner.time_left {
+            metric(
+                "time_left_seconds",
+                "gauge",
+                "Time left until the system IP address expires, zero if inactive.",
+                vec![(String::new(), time_left.num_seconds().to_string())],
+            );
+        }
+        if let Some(connections) = inner.connections {
+            metric(
+                "connections",
+                "gauge",
+                "Number of connections registered for the account.",
+                vec![(String::new(), connections.to_string())],
+            );
+        }
+        if let Some(last_success) = inner.last_success {
+            metric(
+                "last_success_timestamp_seconds",
+                "gauge",
+                "Unix time of the last successful status check.",
+                vec![(String::new(), last_success.timestamp().to_string())],
+            );
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.record(Operation::Status, Duration::from_millis(300), None);
+        metrics.record(
+            Operation::Approve,
+            Duration::from_secs(20),
+            Some(&Error::MalformedIp(String::from("10.21.0"))),
+        );
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE netaccess_requests_total counter",
+            "netaccess_requests_total{operation=\"status\",result=\"success\"} 1",
+            "netaccess_requests_total{operation=\"approve\",result=\"error\"} 1",
+            "netaccess_requests_total{operation=\"revoke\",result=\"success\"} 0",
+            "netaccess_request_duration_seconds_bucket{operation=\"status\",le=\"0.25\"} 0",
+            "netaccess_request_duration_seconds_bucket{operation=\"status\",le=\"0.5\"} 1",
+            "netaccess_request_duration_seconds_bucket{operation=\"status\",le=\"+Inf\"} 1",
+            "netaccess_request_duration_seconds_bucket{operation=\"approve\",le=\"10\"} 0",
+            "netaccess_request_duration_seconds_bucket{operation=\"approve\",le=\"+Inf\"} 1",
+            "netaccess_request_duration_seconds_sum{operation=\"approve\"} 20",
+            "netaccess_errors_total{category=\"invalid_input\"} 1",
+        ] {
+            assert!(
+                text.lines().any(|rendered| rendered == line),
+                "{line} in {text}"
+            );
+        }
+        assert!(!text.contains("netaccess_time_left_seconds"));
+    }
+}
@@ -0,0 +1,64 @@
+use netaccess::AccountManager;
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time,
+};
+
+const METRICS_PATH: &str = "/metrics";
+
+/// Time a client gets to send its request before the connection is dropped, so that idle
+/// connections do not pile up.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn respond(mut stream: TcpStream, account_manager: &AccountManager) -> io::Result<()> {
+    // Only the request line matters, which fits in the first read of any sane scraper request
+    let mut buf = [0; 1024];
+    let read = time::timeout(READ_TIMEOUT, stream.read(&mut buf))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+    let request = String::from_utf8_lossy(&buf[..read]);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => ("200 OK", account_manager.metrics().render()),
+        (Some("GET"), _) => (
+            "404 Not Found",
+            String::from("Metrics are served at /metrics\n"),
+        ),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves the metrics of the account manager in the Prometheus text format at `/metrics` on the
+/// given address, until the returned task is aborted.
+pub async fn serve(
+    addr: SocketAddr,
+    account_manager: Arc<AccountManager>,
+) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    Ok(tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let account_manager = Arc::clone(&account_manager);
+            tokio::spawn(async move {
+                // Scrapers retry on their own, so failed responses are not worth reporting
+                let _ = respond(stream, &account_manager).await;
+            });
+        }
+    }))
+}
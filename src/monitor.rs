@@ -1,71 +1,184 @@
 use crate::{
-    account_manager::{AccountManager, SystemStatus},
+    account_manager::{AccountManager, Connection, Outcome, Report, SystemStatus},
+    connectivity::{Probe, Unreachable},
+    portal_client::PortalClient,
+    schedule::{Action, Phase, Schedule},
     user::User,
 };
 use anyhow::Context;
-use std::{net::IpAddr, sync::Arc, time::Duration};
+use chrono::Local;
+use reqwest::Url;
+use std::{
+    fmt::{self, Display, Formatter},
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
     select,
     sync::{mpsc, oneshot, watch},
     task::JoinHandle,
     time,
 };
+use tracing::Instrument;
 
+/// State of the monitor loop, sent to observers whenever it changes.
 #[derive(Debug)]
 pub enum State {
+    /// The system IP address is in the state the schedule asks for and the monitor is sleeping
+    /// until the next check.
     Suspended {
+        /// How long the monitor sleeps for.
         duration: Duration,
+        /// Current phase of the schedule.
+        phase: Phase,
+        /// Send on this to wake the monitor up early.
         wake_sender: oneshot::Sender<()>,
     },
+    /// The monitor is querying the portal for the status of the system IP address.
     CheckingStatus,
+    /// The monitor is probing whether the internet is actually reachable.
+    Probing,
+    /// The probe disagrees with the portal on whether the system IP address is active.
+    Mismatch(Mismatch),
+    /// The monitor is approving the inactive system IP address.
     Approving(IpAddr),
+    /// The portal approved the system IP address, sent only when an approval actually took place
+    /// rather than finding the IP address active already.
+    Approved {
+        /// The approved IP address.
+        ip: IpAddr,
+        /// The connection as registered by the portal after approval.
+        connection: Connection,
+    },
+    /// The monitor is revoking the active system IP address as the schedule asks for.
+    Revoking(IpAddr),
+    /// The monitor ran into an error and waits for a retry.
     Error {
+        /// The error encountered.
         error: anyhow::Error,
+        /// Send on this to retry, dropping it stops the monitor.
         retry_sender: oneshot::Sender<()>,
     },
 }
 
+impl State {
+    /// Short name of the state in kebab case, such as `checking-status`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Suspended { .. } => "suspended",
+            Self::CheckingStatus => "checking-status",
+            Self::Probing => "probing",
+            Self::Mismatch(_) => "mismatch",
+            Self::Approving(_) => "approving",
+            Self::Approved { .. } => "approved",
+            Self::Revoking(_) => "revoking",
+            Self::Error { .. } => "error",
+        }
+    }
+}
+
+/// Disagreement between the connection state reported by the portal and actual connectivity.
+#[derive(Debug, Clone)]
+pub enum Mismatch {
+    /// The portal marks the system IP address active, yet the internet is unreachable.
+    ActiveButUnreachable(Unreachable),
+    /// The portal marks the system IP address inactive, yet the internet is reachable through
+    /// the given probe target.
+    InactiveButReachable(Url),
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ActiveButUnreachable(unreachable) => {
+                write!(f, "Portal marks IP address active. {unreachable}")
+            }
+            Self::InactiveButReachable(target) => write!(
+                f,
+                "Portal marks IP address inactive, yet {target} is reachable"
+            ),
+        }
+    }
+}
+
+/// Settings of the monitor loop.
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    /// Label or form value of the duration to approve for, or the portal's default duration.
+    pub approve_duration: Option<String>,
+    /// How long to sleep between checks.
+    pub suspend_duration: Duration,
+    /// When to keep the system IP address approved, let it lapse or revoke it.
+    pub schedule: Schedule,
+    /// Probe checking whether the internet is actually reachable on every check, if any.
+    pub probe: Option<Probe>,
+    /// Whether to approve again while the portal marks the system IP address active but the
+    /// internet is unreachable. Approval is forced once until the internet is reachable again.
+    pub reapprove_unreachable: bool,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            approve_duration: None,
+            suspend_duration: Duration::from_secs(5 * 60),
+            schedule: Schedule::default(),
+            probe: None,
+            reapprove_unreachable: false,
+        }
+    }
+}
+
+/// Periodically checks the status of the system IP address in the background and approves it
+/// whenever it becomes inactive, or lets it lapse or revokes it as a [`Schedule`] asks for.
 #[derive(Debug)]
-pub struct Monitor {
+pub struct Monitor<P: PortalClient = AccountManager> {
     handle: Option<JoinHandle<()>>,
-    account_manager: Arc<AccountManager>,
+    portal: Arc<P>,
 }
 
-impl Monitor {
-    pub fn new(account_manager: &Arc<AccountManager>) -> Self {
+impl<P: PortalClient> Monitor<P> {
+    /// Creates a monitor which is not started yet.
+    pub fn new(portal: &Arc<P>) -> Self {
         Self {
             handle: None,
-            account_manager: Arc::clone(account_manager),
+            portal: Arc::clone(portal),
         }
     }
 
+    /// Starts the monitor loop on a background task with the given settings. Status updates and
+    /// state changes are sent on the given channels. Does nothing if already started.
     pub fn start(
         &mut self,
         user: User,
-        duration_index: usize,
-        suspend_duration: Duration,
+        config: MonitorConfig,
         status_sender: watch::Sender<Option<SystemStatus>>,
         state_sender: mpsc::Sender<State>,
     ) {
         if self.handle.is_some() {
             return;
         }
-        let account_manager = Arc::clone(&self.account_manager);
+        let portal = Arc::clone(&self.portal);
         self.handle = tokio::spawn(async move {
-            loop {
+            // Whether approval was forced since the internet last became unreachable
+            let mut reapproved = false;
+            for cycle in 1u64.. {
                 let result = Self::run(
                     &user,
-                    &account_manager,
-                    duration_index,
-                    suspend_duration,
+                    portal.as_ref(),
+                    &config,
+                    &mut reapproved,
                     &status_sender,
                     &state_sender,
                 )
+                .instrument(tracing::info_span!("monitor_cycle", cycle))
                 .await;
                 let Err(err) = result else {
                     // Proceeding to the next iteration of the loop
                     continue;
                 };
+                tracing::warn!(cycle, error = format!("{err:#}"), "Monitor cycle failed");
                 let (retry_sender, retry_receiver) = oneshot::channel();
                 let result = state_sender
                     .send(State::Error {
@@ -89,9 +202,9 @@ impl Monitor {
 
     async fn run(
         user: &User,
-        account_manager: &AccountManager,
-        duration_index: usize,
-        suspend_duration: Duration,
+        portal: &P,
+        config: &MonitorConfig,
+        reapproved: &mut bool,
         status_sender: &watch::Sender<Option<SystemStatus>>,
         state_sender: &mpsc::Sender<State>,
     ) -> anyhow::Result<()> {
@@ -105,34 +218,375 @@ impl Monitor {
         }
 
         send_msg!(State::CheckingStatus);
-        let status = account_manager.status(user).await?;
+        let status = portal.status(user).await?;
 
         status_sender
             .send(status.system_status.into())
             .context("State channel closed")?;
 
-        let SystemStatus { ip, connection } = status.system_status;
-
-        if !connection.is_active() {
-            send_msg!(State::Approving(ip));
-            account_manager.approve(user, duration_index, false).await?;
-        } else {
-            let (wake_sender, wake_receiver) = oneshot::channel();
-            send_msg!(State::Suspended {
-                duration: suspend_duration,
-                wake_sender,
-            });
-            select! {
-                _ = time::sleep(suspend_duration) => {}
-                _ = wake_receiver => {}
+        let SystemStatus { ip, connection, .. } = status.system_status;
+        let phase = config.schedule.phase(Local::now());
+        tracing::info!(
+            %ip,
+            active = connection.is_active(),
+            time_left = connection.time_left.num_seconds(),
+            phase = %phase.action,
+            "Checked status"
+        );
+
+        let reachability = match &config.probe {
+            Some(probe) => {
+                send_msg!(State::Probing);
+                Some(probe.check().await.cloned())
+            }
+            None => None,
+        };
+        let mismatch = match reachability {
+            Some(Err(unreachable)) if connection.is_active() => {
+                Some(Mismatch::ActiveButUnreachable(unreachable))
+            }
+            Some(Ok(target)) if !connection.is_active() => {
+                Some(Mismatch::InactiveButReachable(target))
+            }
+            Some(Ok(_)) => {
+                *reapproved = false;
+                None
+            }
+            _ => None,
+        };
+        let reapprove = config.reapprove_unreachable
+            && !*reapproved
+            && matches!(mismatch, Some(Mismatch::ActiveButUnreachable(_)));
+        if let Some(mismatch) = mismatch {
+            tracing::warn!(%mismatch, "Probe disagrees with portal");
+            send_msg!(State::Mismatch(mismatch));
+        }
+
+        match phase.action {
+            Action::Keep if !connection.is_active() || reapprove => {
+                send_msg!(State::Approving(ip));
+                *reapproved |= reapprove;
+                let Report { ip, outcome, .. } = portal
+                    .approve(user, config.approve_duration.as_deref(), reapprove)
+                    .await?;
+                tracing::info!(%ip, forced = reapprove, ?outcome, "Approved");
+                if let Outcome::Approved { connection } = outcome {
+                    status_sender
+                        .send(
+                            SystemStatus {
+                                ip,
+                                connection,
+                                ..status.system_status
+                            }
+                            .into(),
+                        )
+                        .context("State channel closed")?;
+                    send_msg!(State::Approved { ip, connection });
+                }
+            }
+            Action::Revoke if connection.is_active() => {
+                send_msg!(State::Revoking(ip));
+                let Report { outcome, .. } = portal.revoke(user, None).await?;
+                tracing::info!(%ip, ?outcome, "Revoked as scheduled");
+                if let Outcome::Revoked = outcome {
+                    status_sender
+                        .send(
+                            SystemStatus {
+                                connection: Default::default(),
+                                ..status.system_status
+                            }
+                            .into(),
+                        )
+                        .context("State channel closed")?;
+                }
+            }
+            _ => {
+                // Wake up when the phase changes, so that it takes effect right away
+                let duration = phase
+                    .until
+                    .and_then(|until| (until - Local::now()).to_std().ok())
+                    .map_or(config.suspend_duration, |duration| {
+                        duration.min(config.suspend_duration)
+                    });
+                tracing::debug!(?duration, "Suspending");
+                let (wake_sender, wake_receiver) = oneshot::channel();
+                send_msg!(State::Suspended {
+                    duration,
+                    phase,
+                    wake_sender,
+                });
+                select! {
+                    _ = time::sleep(duration) => {}
+                    _ = wake_receiver => {}
+                }
             }
         }
         Ok(())
     }
 
+    /// Stops the monitor loop if it is running.
     pub fn stop(&mut self) {
         if let Some(handle) = self.handle.take() {
             handle.abort();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        connectivity::tests::serve,
+        portal_client::fake::{FakePortal, FAKE_IP},
+    };
+    use tokio::time::Instant;
+
+    const SUSPEND_DURATION: Duration = Duration::from_secs(5 * 60);
+    const HOUR: &str = "60 minutes";
+
+    struct Harness {
+        _monitor: Monitor<FakePortal>,
+        portal: Arc<FakePortal>,
+        status_receiver: watch::Receiver<Option<SystemStatus>>,
+        state_receiver: mpsc::Receiver<State>,
+    }
+
+    impl Harness {
+        fn start(portal: FakePortal) -> Self {
+            Self::start_with_config(portal, MonitorConfig::default())
+        }
+
+        fn start_with_schedule(portal: FakePortal, schedule: Schedule) -> Self {
+            Self::start_with_config(
+                portal,
+                MonitorConfig {
+                    schedule,
+                    ..Default::default()
+                },
+            )
+        }
+
+        fn start_with_config(portal: FakePortal, config: MonitorConfig) -> Self {
+            let portal = Arc::new(portal);
+            let mut monitor = Monitor::new(&portal);
+            let (status_sender, status_receiver) = watch::channel(None);
+            let (state_sender, state_receiver) = mpsc::channel(1);
+            monitor.start(
+                User::new(String::from("XX19X001"), String::from("password")),
+                MonitorConfig {
+                    approve_duration: Some(String::from(HOUR)),
+                    suspend_duration: SUSPEND_DURATION,
+                    ..config
+                },
+                status_sender,
+                state_sender,
+            );
+            Self {
+                _monitor: monitor,
+                portal,
+                status_receiver,
+                state_receiver,
+            }
+        }
+
+        async fn next(&mut self) -> State {
+            self.state_receiver.recv().await.expect("Monitor stopped")
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn approves_inactive_ip() {
+        let mut harness = Harness::start(FakePortal::default());
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Approving(ip) if ip == FAKE_IP));
+        assert!(matches!(
+            harness.next().await,
+            State::Approved { ip, connection } if ip == FAKE_IP && connection.is_active()
+        ));
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(
+            matches!(harness.next().await, State::Suspended { duration, .. } if duration == SUSPEND_DURATION)
+        );
+
+        assert_eq!(harness.portal.state().approvals, vec![HOUR]);
+        let status = harness.status_receiver.borrow().expect("Status sent");
+        assert!(status.connection.is_active());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn renews_after_expiry() {
+        let mut harness = Harness::start(FakePortal::default());
+        let start = Instant::now();
+        // Wake senders are held on to, as dropping them wakes the monitor up
+        let mut wake_senders = vec![];
+        let mut approvals = 0;
+
+        while approvals < 2 {
+            match harness.next().await {
+                State::Suspended { wake_sender, .. } => wake_senders.push(wake_sender),
+                State::Approving(_) => approvals += 1,
+                _ => {}
+            }
+        }
+
+        // An hour long approval lapses right after the last of the suspensions covering it
+        assert_eq!(start.elapsed(), Duration::from_secs(60 * 60));
+        assert_eq!(wake_senders.len(), 12);
+        assert!(matches!(harness.next().await, State::Approved { .. }));
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert_eq!(harness.portal.state().approvals, vec![HOUR; 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wakes_up_early() {
+        let mut harness = Harness::start(FakePortal::default());
+
+        let wake_sender = loop {
+            if let State::Suspended { wake_sender, .. } = harness.next().await {
+                break wake_sender;
+            }
+        };
+        let start = Instant::now();
+        wake_sender.send(()).expect("Monitor is suspended");
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(start.elapsed().is_zero());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_after_error() {
+        let portal = FakePortal::default();
+        portal.state().failures = 1;
+        let mut harness = Harness::start(portal);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        let State::Error { retry_sender, .. } = harness.next().await else {
+            panic!("Expected error state");
+        };
+        assert!(harness.portal.state().approvals.is_empty());
+
+        retry_sender.send(()).expect("Monitor awaits retry");
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Approving(_)));
+        assert_eq!(harness.portal.state().approvals, vec![HOUR]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_when_retry_abandoned() {
+        let portal = FakePortal::default();
+        portal.state().failures = 1;
+        let mut harness = Harness::start(portal);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Error { .. }));
+        assert!(harness.state_receiver.recv().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn revokes_when_scheduled() {
+        let portal = FakePortal::default();
+        portal.state().active_until = Some(Instant::now() + Duration::from_secs(60 * 60));
+        let schedule = Schedule::new(vec!["revoke *".parse().unwrap()]);
+        let mut harness = Harness::start_with_schedule(portal, schedule);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Revoking(ip) if ip == FAKE_IP));
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(
+            harness.next().await,
+            State::Suspended {
+                phase: Phase {
+                    action: Action::Revoke,
+                    ..
+                },
+                ..
+            }
+        ));
+
+        assert_eq!(harness.portal.state().revocations, 1);
+        assert!(harness.portal.state().approvals.is_empty());
+        let status = harness.status_receiver.borrow().expect("Status sent");
+        assert!(!status.connection.is_active());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lets_ip_lapse_when_scheduled() {
+        let schedule = Schedule::new(vec!["lapse *".parse().unwrap()]);
+        let mut harness = Harness::start_with_schedule(FakePortal::default(), schedule);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(
+            harness.next().await,
+            State::Suspended { duration, phase: Phase { action: Action::Lapse, until: None }, .. }
+                if duration == SUSPEND_DURATION
+        ));
+        assert!(harness.portal.state().approvals.is_empty());
+    }
+
+    fn active_portal() -> FakePortal {
+        let portal = FakePortal::default();
+        portal.state().active_until = Some(Instant::now() + Duration::from_secs(60 * 60));
+        portal
+    }
+
+    #[tokio::test]
+    async fn records_mismatch() {
+        let (url, _) = serve(&["HTTP/1.1 302 Found"]).await;
+        let config = MonitorConfig {
+            probe: Some(Probe::new(vec![url]).unwrap()),
+            ..Default::default()
+        };
+        let mut harness = Harness::start_with_config(active_portal(), config);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Probing));
+        assert!(matches!(
+            harness.next().await,
+            State::Mismatch(Mismatch::ActiveButUnreachable(_))
+        ));
+        assert!(matches!(harness.next().await, State::Suspended { .. }));
+        assert!(harness.portal.state().approvals.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reapproves_once_when_unreachable() {
+        let (url, served) = serve(&["HTTP/1.1 302 Found"]).await;
+        let config = MonitorConfig {
+            probe: Some(Probe::new(vec![url]).unwrap()),
+            reapprove_unreachable: true,
+            ..Default::default()
+        };
+        let mut harness = Harness::start_with_config(active_portal(), config);
+
+        let mut mismatches = 0;
+        loop {
+            match harness.next().await {
+                State::Mismatch(_) => mismatches += 1,
+                State::Suspended { .. } => break,
+                _ => {}
+            }
+        }
+
+        // Approval is forced after the first mismatch alone, as the internet stays unreachable
+        assert_eq!(mismatches, 2);
+        assert_eq!(served.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(harness.portal.state().approvals, vec![HOUR]);
+    }
+
+    #[tokio::test]
+    async fn no_mismatch_when_reachable() {
+        let (url, _) = serve(&["HTTP/1.1 204 No Content"]).await;
+        let config = MonitorConfig {
+            probe: Some(Probe::new(vec![url]).unwrap()),
+            reapprove_unreachable: true,
+            ..Default::default()
+        };
+        let mut harness = Harness::start_with_config(active_portal(), config);
+
+        assert!(matches!(harness.next().await, State::CheckingStatus));
+        assert!(matches!(harness.next().await, State::Probing));
+        assert!(matches!(harness.next().await, State::Suspended { .. }));
+        assert!(harness.portal.state().approvals.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use netaccess::{State, SystemStatus};
+use tokio::sync::{mpsc, watch};
+
+/// Forwards the states of the monitor to the returned channel, handing each one to `observe`
+/// along with the status of the system IP address at that point. Observers react to
+/// transitions without holding up the interface at the end of the channel.
+pub fn tap(
+    status_receiver: watch::Receiver<Option<SystemStatus>>,
+    mut state_receiver: mpsc::Receiver<State>,
+    mut observe: impl FnMut(&State, Option<&SystemStatus>) + Send + 'static,
+) -> mpsc::Receiver<State> {
+    let (state_sender, tapped_receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        while let Some(state) = state_receiver.recv().await {
+            let status = *status_receiver.borrow();
+            observe(&state, status.as_ref());
+            if state_sender.send(state).await.is_err() {
+                break;
+            }
+        }
+    });
+    tapped_receiver
+}
+
+/// Monitor states and statuses shared by the tests of the observers.
+#[cfg(test)]
+pub mod test_util {
+    use netaccess::{Connection, SystemStatus};
+
+    pub const IP: [u8; 4] = [10, 21, 0, 17];
+
+    pub fn active(minutes: i64) -> Connection {
+        Connection::new(chrono::Duration::minutes(minutes), None, true)
+    }
+
+    pub fn status(connection: Connection) -> SystemStatus {
+        SystemStatus {
+            ip: IP.into(),
+            connection,
+            clock_skew: chrono::Duration::zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_util::*, *};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn forwards_states_with_status() {
+        let (status_sender, status_receiver) = watch::channel(None);
+        let (state_sender, state_receiver) = mpsc::channel(1);
+        let observed = Arc::new(Mutex::new(vec![]));
+        let mut tapped_receiver = tap(status_receiver, state_receiver, {
+            let observed = Arc::clone(&observed);
+            move |state, status| {
+                let status = status.map(|status| status.connection.is_active());
+                observed.lock().unwrap().push((state.name(), status));
+            }
+        });
+
+        state_sender.send(State::CheckingStatus).await.unwrap();
+        assert!(matches!(
+            tapped_receiver.recv().await,
+            Some(State::CheckingStatus)
+        ));
+        status_sender.send_replace(Some(status(active(60))));
+        state_sender
+            .send(State::Approving(IP.into()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            tapped_receiver.recv().await,
+            Some(State::Approving(_))
+        ));
+
+        drop(state_sender);
+        assert!(tapped_receiver.recv().await.is_none());
+        assert_eq!(
+            *observed.lock().unwrap(),
+            vec![("checking-status", None), ("approving", Some(true))]
+        );
+    }
+}
@@ -1,11 +1,12 @@
-use crate::{account_manager::SystemStatus, monitor::State};
 use anyhow::bail;
+use chrono::{DateTime, Local};
 use crossterm::{
     cursor::{Hide, Show},
     event::{self, Event, KeyCode},
     execute,
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use netaccess::{Action, DurationStyle, Mismatch, Phase, State, SystemStatus};
 use std::{
     io,
     sync::{
@@ -26,35 +27,26 @@ use tui::{
     Frame, Terminal,
 };
 
-pub fn format_duration(duration: &chrono::Duration) -> String {
-    if duration.is_zero() {
-        return String::new();
+pub fn clock_skew_warning(status: &SystemStatus, style: DurationStyle) -> Option<String> {
+    if !status.is_clock_skewed() {
+        return None;
     }
-    let mut fragments = Vec::with_capacity(3);
-    macro_rules! push {
-        ( $unit:expr ) => {
-            if $unit > 0 {
-                fragments.push(format!("{} {}", $unit, stringify!($unit)));
-            }
-        };
-    }
-    let minutes = duration.num_minutes() % 60;
-    push!(minutes);
-    let hours = duration.num_hours() % 24;
-    push!(hours);
-    let days = duration.num_days();
-    push!(days);
-    fragments
-        .into_iter()
-        .rev()
-        .collect::<Vec<String>>()
-        .join(", ")
+    Some(format!(
+        "System clock is {} {} the portal clock, consider syncing it",
+        style.format(&status.clock_skew.abs()),
+        if status.clock_skew > chrono::Duration::zero() {
+            "behind"
+        } else {
+            "ahead of"
+        }
+    ))
 }
 
 pub fn run(
     status_receiver: watch::Receiver<Option<SystemStatus>>,
     state_receiver: mpsc::Receiver<State>,
     cancellation_token: CancellationToken,
+    duration_style: DurationStyle,
 ) -> JoinHandle<Result<(), anyhow::Error>> {
     tokio::spawn(async move {
         terminal::enable_raw_mode()?;
@@ -67,6 +59,7 @@ pub fn run(
             status_receiver,
             state_receiver,
             cancellation_token,
+            duration_style,
         )
         .await;
         terminal::disable_raw_mode()?;
@@ -146,18 +139,44 @@ async fn ui_event_loop<B: Backend>(
     status_receiver: watch::Receiver<Option<SystemStatus>>,
     mut state_receiver: mpsc::Receiver<State>,
     cancellation_token: CancellationToken,
+    duration_style: DurationStyle,
 ) -> anyhow::Result<()> {
     let mut key_input_reader: Option<KeyInputReader> = None;
+    // Phase of the schedule as of the last suspension, shown while the monitor is busy as well
+    let mut phase: Option<Phase> = None;
+    // Mismatches between the portal and the probe are only sent once, so they are tracked here
+    let mut mismatches = MismatchLog::default();
     while let Some(state) = state_receiver.recv().await {
         if let Some(reader) = key_input_reader.take() {
             let _ = reader.cancel().await?;
         }
+        match &state {
+            State::Suspended {
+                phase: suspended_phase,
+                ..
+            } => phase = Some(*suspended_phase),
+            State::Mismatch(mismatch) => {
+                mismatches.count += 1;
+                mismatches.last = Some((Local::now(), mismatch.clone()));
+            }
+            _ => {}
+        }
 
-        terminal.draw(|frame| render_ui(frame, status_receiver.borrow().as_ref(), &state))?;
+        terminal.draw(|frame| {
+            render_ui(
+                frame,
+                status_receiver.borrow().as_ref(),
+                phase.as_ref(),
+                &mismatches,
+                &state,
+                duration_style,
+            )
+        })?;
 
         key_input_reader = match state {
             State::Suspended {
                 duration: _,
+                phase: _,
                 wake_sender,
             } => {
                 let cancellation_token = cancellation_token.clone();
@@ -190,25 +209,50 @@ async fn ui_event_loop<B: Backend>(
     Ok(())
 }
 
-fn render_ui<B: Backend>(frame: &mut Frame<B>, status: Option<&SystemStatus>, state: &State) {
+#[derive(Debug, Default)]
+struct MismatchLog {
+    count: usize,
+    last: Option<(DateTime<Local>, Mismatch)>,
+}
+
+fn render_ui<B: Backend>(
+    frame: &mut Frame<B>,
+    status: Option<&SystemStatus>,
+    phase: Option<&Phase>,
+    mismatches: &MismatchLog,
+    state: &State,
+    duration_style: DurationStyle,
+) {
     let rects = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Percentage(100)])
         .split(frame.size());
 
     /*
-     * Max 3 status items
+     * Max 4 status items
+     * 1 for schedule phase
+     * 1 for probe mismatches
      * 2 for monitor state (header + text)
      * 3 for controls (header + 2 input texts)
      */
-    let mut list_items = Vec::with_capacity(3 + 2 + 3);
+    let mut list_items = Vec::with_capacity(4 + 1 + 1 + 2 + 3);
 
-    if let Some(status_items) = status.map(status_items) {
+    if let Some(status_items) = status.map(|status| status_items(status, duration_style)) {
         list_items.extend(status_items);
     }
+    if let Some(phase_item) = phase.and_then(|phase| phase_item(phase, duration_style)) {
+        list_items.push(phase_item);
+    }
+    if let Some((time, mismatch)) = &mismatches.last {
+        list_items.push(ListItem::new(format!(
+            "Probe mismatches: {}, last at {}: {mismatch}",
+            mismatches.count,
+            time.format("%a %H:%M")
+        )));
+    }
 
     list_items.push(ListItem::new("----- Monitor State -----"));
-    list_items.push(state_item(state));
+    list_items.push(state_item(state, duration_style));
 
     let control_items = control_items(state);
     if !control_items.is_empty() {
@@ -219,8 +263,8 @@ fn render_ui<B: Backend>(frame: &mut Frame<B>, status: Option<&SystemStatus>, st
     frame.render_widget(List::new(list_items), rects[0]);
 }
 
-fn status_items(status: &SystemStatus) -> Vec<ListItem> {
-    let mut items = Vec::with_capacity(3);
+fn status_items(status: &SystemStatus, duration_style: DurationStyle) -> Vec<ListItem<'_>> {
+    let mut items = Vec::with_capacity(4);
     items.push(ListItem::new(format!("IP address: {}", status.ip)));
     items.push(ListItem::new(format!(
         "Connection state: {}",
@@ -233,20 +277,53 @@ fn status_items(status: &SystemStatus) -> Vec<ListItem> {
     if status.connection.is_active() {
         items.push(ListItem::new(format!(
             "Time left: {}",
-            format_duration(&status.connection.time_left)
+            duration_style.format(&status.connection.time_left)
         )));
     }
+    if let Some(warning) = clock_skew_warning(status, duration_style) {
+        items.push(ListItem::new(format!("Warning: {warning}")));
+    }
     items
 }
 
-fn state_item(state: &State) -> ListItem {
+/// Describes the phase of the schedule, unless there is none keeping the IP address approved
+/// around the clock.
+fn phase_item(phase: &Phase, duration_style: DurationStyle) -> Option<ListItem<'static>> {
+    let action = match phase.action {
+        Action::Keep if phase.until.is_none() => return None,
+        Action::Keep => "keep approved",
+        Action::Lapse => "let lapse",
+        Action::Revoke => "revoke",
+    };
+    Some(ListItem::new(match phase.until {
+        Some(until) => format!(
+            "Schedule: {action} until {} (in {})",
+            until.format("%a %H:%M"),
+            duration_style.format(&(until - chrono::Local::now()))
+        ),
+        None => format!("Schedule: {action}"),
+    }))
+}
+
+fn state_item(state: &State, duration_style: DurationStyle) -> ListItem<'_> {
     match state {
         State::Suspended {
             duration,
+            phase: _,
             wake_sender: _,
-        } => ListItem::new(format!("Suspended for {duration:?}")),
+        } => ListItem::new(format!(
+            "Suspended for {}",
+            duration_style.format_std(duration)
+        )),
         State::CheckingStatus => ListItem::new("Checking status"),
+        State::Probing => ListItem::new("Probing internet connectivity"),
+        State::Mismatch(mismatch) => ListItem::new(mismatch.to_string()),
         State::Approving(ip) => ListItem::new(format!("Approving IP {ip}")),
+        State::Approved { ip, connection } => ListItem::new(format!(
+            "Approved IP {ip} for {}",
+            duration_style.format(&connection.time_left)
+        )),
+        State::Revoking(ip) => ListItem::new(format!("Revoking IP {ip} as scheduled")),
         State::Error {
             error,
             retry_sender: _,
@@ -254,10 +331,11 @@ fn state_item(state: &State) -> ListItem {
     }
 }
 
-fn control_items(state: &State) -> Vec<ListItem> {
+fn control_items(state: &State) -> Vec<ListItem<'_>> {
     match state {
         State::Suspended {
             duration: _,
+            phase: _,
             wake_sender: _,
         } => {
             vec![
@@ -266,7 +344,11 @@ fn control_items(state: &State) -> Vec<ListItem> {
             ]
         }
         State::CheckingStatus => vec![],
+        State::Probing => vec![],
+        State::Mismatch(_) => vec![],
         State::Approving(_) => vec![],
+        State::Approved { .. } => vec![],
+        State::Revoking(_) => vec![],
         State::Error {
             error: _,
             retry_sender: _,
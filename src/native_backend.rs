@@ -0,0 +1,447 @@
+use crate::{
+    http_config::HttpConfig,
+    tls::{self, HandshakeFailure, TlsConfig, TlsError, TlsReport},
+};
+use reqwest::{tls::Version, Certificate, ClientBuilder};
+
+/// Configures the builder to use the TLS library of the platform, as earlier versions did:
+/// OpenSSL with its security level lowered, or SChannel on Windows and Security.framework on
+/// macOS. Only TLS 1.2 and 1.3 are allowed, and the CA bundles are trusted along with the root
+/// certificates of the system.
+pub(crate) fn configure(
+    config: &TlsConfig,
+    mut builder: ClientBuilder,
+) -> Result<ClientBuilder, TlsError> {
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+    openssl_backend::lower_security_level()?;
+    builder = builder.use_native_tls().min_tls_version(Version::TLS_1_2);
+    for path in &config.ca_bundles {
+        let certificates = tls::read_ca_bundle(path)?
+            .iter()
+            .filter_map(|certificate| Certificate::from_der(certificate).ok())
+            .collect::<Vec<_>>();
+        if certificates.is_empty() {
+            return Err(TlsError::EmptyCaBundle(path.clone()));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+pub(crate) use openssl_backend::{check, handshake_failure};
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "ios"))]
+pub(crate) use platform_backend::{check, handshake_failure};
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+mod openssl_backend {
+    use super::*;
+    use crate::tls::CertificateInfo;
+    use openssl::{
+        error::ErrorStack,
+        ssl::{
+            Error as SslError, HandshakeError, SslConnector, SslMethod, SslVerifyMode, SslVersion,
+        },
+        x509::{X509VerifyResult, X509},
+    };
+    use openssl_sys::{BIO, CONF};
+    use std::{
+        error::Error,
+        ffi::{c_char, c_int, c_long, c_ulong},
+        io, net, ptr,
+        sync::OnceLock,
+    };
+
+    /// Configuration lowering the security level of OpenSSL to 1, which earlier versions wrote
+    /// to a file named by `OPENSSL_CONF`. The portal negotiates finite field Diffie-Hellman
+    /// with parameters that OpenSSL rejects as too small otherwise.
+    const CONF: &str = include_str!("../openssl.cnf");
+
+    // Not bound by openssl-sys
+    extern "C" {
+        fn NCONF_load_bio(conf: *mut CONF, bp: *mut BIO, eline: *mut c_long) -> c_int;
+        fn CONF_modules_load(cnf: *const CONF, appname: *const c_char, flags: c_ulong) -> c_int;
+    }
+
+    /// Reasons of OpenSSL errors meaning that the portal and OpenSSL share no protocol version,
+    /// cipher suite or key exchange. Alerts are prefixed with `sslv3` or `tlsv1` before
+    /// OpenSSL 3.2 and with `ssl/tls` since.
+    const INCOMPATIBLE_REASONS: [&str; 6] = [
+        "dh key too small",
+        "unsupported protocol",
+        "no protocols available",
+        "alert handshake failure",
+        "alert insufficient security",
+        "alert protocol version",
+    ];
+
+    /// Applies the configuration to every OpenSSL context created from now on in the process,
+    /// which native-tls picks up as the `system_default` section. Loaded once, nothing is
+    /// written to disk nor to the environment.
+    pub(super) fn lower_security_level() -> Result<(), TlsError> {
+        static LOADED: OnceLock<Result<(), String>> = OnceLock::new();
+        LOADED
+            .get_or_init(|| load_conf().map_err(|err| err.to_string()))
+            .clone()
+            .map_err(TlsError::Platform)
+    }
+
+    fn load_conf() -> Result<(), ErrorStack> {
+        // The configuration of the system is loaded on initialization, which must not replace
+        // this one later on
+        openssl::init();
+        // SAFETY: the buffer outlives the BIO reading it, and both the BIO and the
+        // configuration are freed once the modules are loaded, which copy what they need
+        unsafe {
+            let conf = openssl_sys::NCONF_new(openssl_sys::NCONF_default());
+            if conf.is_null() {
+                return Err(ErrorStack::get());
+            }
+            let bio = openssl_sys::BIO_new_mem_buf(CONF.as_ptr().cast(), CONF.len() as c_int);
+            let loaded = !bio.is_null()
+                && NCONF_load_bio(conf, bio, &mut 0) > 0
+                && CONF_modules_load(conf, ptr::null(), 0) > 0;
+            if !bio.is_null() {
+                openssl_sys::BIO_free_all(bio);
+            }
+            openssl_sys::NCONF_free(conf);
+            if loaded {
+                Ok(())
+            } else {
+                Err(ErrorStack::get())
+            }
+        }
+    }
+
+    /// Classifies the failure behind an OpenSSL error.
+    pub(crate) fn handshake_failure(err: &(dyn Error + 'static)) -> Option<HandshakeFailure> {
+        let stack = err.downcast_ref::<ErrorStack>()?;
+        stack.errors().iter().find_map(|err| {
+            let reason = err.reason()?;
+            if reason == "certificate verify failed" {
+                Some(HandshakeFailure::CertificateRejected)
+            } else if INCOMPATIBLE_REASONS
+                .iter()
+                .any(|incompatible| reason.ends_with(incompatible))
+            {
+                Some(HandshakeFailure::Incompatible)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn connector(config: &TlsConfig) -> Result<SslConnector, TlsError> {
+        lower_security_level()?;
+        let openssl_error = |err: ErrorStack| TlsError::Platform(err.to_string());
+        let mut builder = SslConnector::builder(SslMethod::tls_client()).map_err(openssl_error)?;
+        builder
+            .set_min_proto_version(Some(SslVersion::TLS1_2))
+            .map_err(openssl_error)?;
+        for path in &config.ca_bundles {
+            let certificates = tls::read_ca_bundle(path)?
+                .iter()
+                .filter_map(|certificate| X509::from_der(certificate).ok())
+                .collect::<Vec<_>>();
+            if certificates.is_empty() {
+                return Err(TlsError::EmptyCaBundle(path.clone()));
+            }
+            for certificate in certificates {
+                // Certificates already trusted are rejected as duplicates, which is harmless
+                let _ = builder.cert_store_mut().add_cert(certificate);
+            }
+        }
+        // The outcome of verification is read after the handshake, so that the chain of a
+        // portal that fails it can still be inspected. No data is ever sent
+        builder.set_verify(SslVerifyMode::NONE);
+        Ok(builder.build())
+    }
+
+    fn handshake(
+        connector: &SslConnector,
+        host: &str,
+        stream: net::TcpStream,
+    ) -> io::Result<TlsReport> {
+        let into_io_error = |err: SslError| err.into_io_error().unwrap_or_else(io::Error::other);
+        let stream = connector.connect(host, stream).map_err(|err| match err {
+            HandshakeError::SetupFailure(stack) => io::Error::other(stack),
+            HandshakeError::Failure(stream) | HandshakeError::WouldBlock(stream) => {
+                into_io_error(stream.into_error())
+            }
+        })?;
+        let ssl = stream.ssl();
+        Ok(TlsReport {
+            protocol: Some(ssl.version_str().to_owned()),
+            cipher_suite: ssl
+                .current_cipher()
+                .map(|cipher| cipher.standard_name().unwrap_or(cipher.name()).to_owned()),
+            chain: ssl
+                .peer_cert_chain()
+                .into_iter()
+                .flatten()
+                .filter_map(|certificate| certificate.to_der().ok())
+                .filter_map(|certificate| CertificateInfo::parse(&certificate))
+                .collect(),
+            verification_error: match ssl.verify_result() {
+                X509VerifyResult::OK => None,
+                result => Some(result.error_string().to_owned()),
+            },
+        })
+    }
+
+    /// Performs the handshake of `tls-check` with OpenSSL configured as for the portal client,
+    /// so that the protocol and cipher suite reported are the ones the client negotiates.
+    pub(crate) async fn check(config: &HttpConfig, host: &str) -> Result<TlsReport, TlsError> {
+        let connector = connector(&config.tls)?;
+        tls::handshake(config, host, async {
+            let stream = tls::connect(config, host).await?.into_std()?;
+            // OpenSSL blocks on the socket, bounded by the timeout in case the portal stalls
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(config.timeout))?;
+            stream.set_write_timeout(Some(config.timeout))?;
+            let host = host.to_owned();
+            tokio::task::spawn_blocking(move || handshake(&connector, &host, stream))
+                .await
+                .map_err(io::Error::other)?
+        })
+        .await
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use openssl::{
+            asn1::Asn1Time,
+            bn::BigNum,
+            dh::Dh,
+            hash::MessageDigest,
+            pkey::{PKey, Private},
+            rsa::Rsa,
+            ssl::{Ssl, SslContext},
+            x509::{extension::SubjectAlternativeName, X509NameBuilder},
+        };
+        use reqwest::Client;
+        use std::{
+            io::{Read, Write},
+            net::{Ipv4Addr, SocketAddr, TcpListener},
+            path::PathBuf,
+            thread,
+        };
+
+        /// Self-signed certificate of `localhost`.
+        fn certificate() -> (X509, PKey<Private>) {
+            let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+            let mut name = X509NameBuilder::new().unwrap();
+            name.append_entry_by_text("CN", "localhost").unwrap();
+            let name = name.build();
+            let mut builder = X509::builder().unwrap();
+            builder.set_version(2).unwrap();
+            let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+            builder.set_serial_number(&serial).unwrap();
+            builder.set_subject_name(&name).unwrap();
+            builder.set_issuer_name(&name).unwrap();
+            builder.set_pubkey(&key).unwrap();
+            builder
+                .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+                .unwrap();
+            builder
+                .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+                .unwrap();
+            let alternative_name = SubjectAlternativeName::new()
+                .dns("localhost")
+                .build(&builder.x509v3_context(None, None))
+                .unwrap();
+            builder.append_extension(alternative_name).unwrap();
+            builder.sign(&key, MessageDigest::sha256()).unwrap();
+            (builder.build(), key)
+        }
+
+        /// Serves HTTPS like the portal, negotiating finite field Diffie-Hellman with 1024 bit
+        /// parameters only.
+        fn serve_small_dh(certificate: &X509, key: &PKey<Private>) -> SocketAddr {
+            let mut context = SslContext::builder(SslMethod::tls_server()).unwrap();
+            context
+                .set_cipher_list("DHE-RSA-AES128-GCM-SHA256:@SECLEVEL=0")
+                .unwrap();
+            context
+                .set_max_proto_version(Some(SslVersion::TLS1_2))
+                .unwrap();
+            context.set_tmp_dh(&Dh::get_1024_160().unwrap()).unwrap();
+            context.set_certificate(certificate).unwrap();
+            context.set_private_key(key).unwrap();
+            let context = context.build();
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+            let address = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let Ok(mut stream) = Ssl::new(&context).unwrap().accept(stream) else {
+                        continue;
+                    };
+                    let _ = stream.read(&mut [0; 4096]);
+                    let _ =
+                        stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+                }
+            });
+            address
+        }
+
+        fn ca_bundle(certificate: &X509, name: &str) -> PathBuf {
+            let path =
+                std::env::temp_dir().join(format!("netaccess-{name}-{}.pem", std::process::id()));
+            std::fs::write(&path, certificate.to_pem().unwrap()).unwrap();
+            path
+        }
+
+        async fn get(
+            config: &TlsConfig,
+            address: SocketAddr,
+        ) -> reqwest::Result<reqwest::Response> {
+            config
+                .configure(Client::builder().resolve("localhost", address))
+                .unwrap()
+                .build()?
+                .get(format!("https://localhost:{}/", address.port()))
+                .send()
+                .await
+        }
+
+        #[tokio::test]
+        async fn small_dh_accepted() {
+            let (certificate, key) = certificate();
+            let address = serve_small_dh(&certificate, &key);
+            let path = ca_bundle(&certificate, "small-dh");
+            let config = TlsConfig {
+                ca_bundles: vec![path.clone()],
+                pins: vec![],
+            };
+            let response = get(&config, address).await;
+            std::fs::remove_file(path).unwrap();
+
+            assert_eq!(response.unwrap().status(), 204);
+        }
+
+        #[tokio::test]
+        async fn untrusted_certificate_rejected() {
+            let (certificate, key) = certificate();
+            let address = serve_small_dh(&certificate, &key);
+            let err = get(&TlsConfig::default(), address).await.unwrap_err();
+
+            assert_eq!(
+                tls::handshake_failure(&err),
+                Some(HandshakeFailure::CertificateRejected)
+            );
+        }
+
+        #[test]
+        fn small_dh_incompatible_at_default_level() {
+            let (certificate, key) = certificate();
+            let address = serve_small_dh(&certificate, &key);
+            let mut builder = SslConnector::builder(SslMethod::tls_client()).unwrap();
+            builder.set_cipher_list("DEFAULT:@SECLEVEL=2").unwrap();
+            builder.set_verify(SslVerifyMode::NONE);
+            let stream = net::TcpStream::connect(address).unwrap();
+            let err = handshake(&builder.build(), "localhost", stream).unwrap_err();
+
+            assert_eq!(
+                tls::handshake_failure(&err),
+                Some(HandshakeFailure::Incompatible)
+            );
+        }
+
+        #[test]
+        fn negotiated_small_dh_reported() {
+            let (certificate, key) = certificate();
+            let address = serve_small_dh(&certificate, &key);
+            let stream = net::TcpStream::connect(address).unwrap();
+            let report = handshake(
+                &connector(&TlsConfig::default()).unwrap(),
+                "localhost",
+                stream,
+            )
+            .unwrap();
+
+            assert_eq!(report.protocol.as_deref(), Some("TLSv1.2"));
+            assert_eq!(
+                report.cipher_suite.as_deref(),
+                Some("TLS_DHE_RSA_WITH_AES_128_GCM_SHA256")
+            );
+            assert_eq!(report.chain[0].subject, "CN=localhost");
+            assert!(report.verification_error.is_some());
+        }
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "ios"))]
+mod platform_backend {
+    use super::*;
+    use crate::tls::CertificateInfo;
+    use std::{error::Error, io};
+    use tokio_native_tls::{native_tls, TlsConnector};
+
+    /// Failures of the TLS library of the platform are not told apart.
+    pub(crate) fn handshake_failure(_err: &(dyn Error + 'static)) -> Option<HandshakeFailure> {
+        None
+    }
+
+    fn connector(config: &TlsConfig, verify: bool) -> Result<TlsConnector, TlsError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        builder
+            .min_protocol_version(Some(native_tls::Protocol::Tlsv12))
+            .danger_accept_invalid_certs(!verify)
+            .danger_accept_invalid_hostnames(!verify);
+        for path in &config.ca_bundles {
+            let certificates = tls::read_ca_bundle(path)?
+                .iter()
+                .filter_map(|certificate| native_tls::Certificate::from_der(certificate).ok())
+                .collect::<Vec<_>>();
+            if certificates.is_empty() {
+                return Err(TlsError::EmptyCaBundle(path.clone()));
+            }
+            for certificate in certificates {
+                builder.add_root_certificate(certificate);
+            }
+        }
+        builder
+            .build()
+            .map(TlsConnector::from)
+            .map_err(|err| TlsError::Platform(err.to_string()))
+    }
+
+    /// Performs the handshake of `tls-check` with the TLS library of the platform, configured
+    /// as for the portal client. The library reports neither the protocol nor the cipher suite
+    /// negotiated, nor any certificate but the one of the portal. The chain is verified over a
+    /// second connection, so that a portal failing verification can still be inspected.
+    pub(crate) async fn check(config: &HttpConfig, host: &str) -> Result<TlsReport, TlsError> {
+        let (inspecting, verifying) = (
+            connector(&config.tls, false)?,
+            connector(&config.tls, true)?,
+        );
+        tls::handshake(config, host, async {
+            let stream = tls::connect(config, host).await?;
+            let stream = inspecting
+                .connect(host, stream)
+                .await
+                .map_err(io::Error::other)?;
+            let certificate = stream
+                .get_ref()
+                .peer_certificate()
+                .map_err(io::Error::other)?
+                .and_then(|certificate| certificate.to_der().ok());
+            let stream = tls::connect(config, host).await?;
+            let verification_error = verifying.connect(host, stream).await.err();
+            Ok(TlsReport {
+                protocol: None,
+                cipher_suite: None,
+                chain: certificate
+                    .and_then(|certificate| CertificateInfo::parse(&certificate))
+                    .into_iter()
+                    .collect(),
+                verification_error: verification_error.map(|err| err.to_string()),
+            })
+        })
+        .await
+    }
+}
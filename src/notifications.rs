@@ -0,0 +1,205 @@
+use crate::monitor_tap;
+use clap::ValueEnum;
+use netaccess::{DurationStyle, State, SystemStatus};
+use tokio::sync::{mpsc, watch};
+
+/// Time left below which an active connection is considered to expire soon, in seconds.
+const EXPIRY_WARNING: i64 = 15 * 60;
+
+/// Monitor events that raise a desktop notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NotifyEvent {
+    /// The system IP address got approved
+    Approval,
+    /// The system IP address expires in less than 15 minutes
+    Expiry,
+    /// The monitor ran into an error other than rejected credentials and awaits a retry
+    Error,
+    /// The portal rejected the credentials
+    Credentials,
+}
+
+impl NotifyEvent {
+    pub fn is_supported() -> bool {
+        cfg!(all(unix, not(target_os = "macos")))
+    }
+}
+
+struct Notification {
+    summary: &'static str,
+    body: String,
+}
+
+struct Notifier {
+    events: Vec<NotifyEvent>,
+    duration_style: DurationStyle,
+    expiry_warned: bool,
+}
+
+impl Notifier {
+    fn notification(
+        &mut self,
+        state: &State,
+        status: Option<&SystemStatus>,
+    ) -> Option<Notification> {
+        let connection = status.map(|status| status.connection).unwrap_or_default();
+        if connection.is_active() && connection.time_left.num_seconds() > EXPIRY_WARNING {
+            self.expiry_warned = false;
+        }
+
+        let (event, notification) = match state {
+            State::Approved { ip, connection } => (
+                NotifyEvent::Approval,
+                Notification {
+                    summary: "IP address approved",
+                    body: format!(
+                        "{ip} is active for {}",
+                        self.duration_style.format(&connection.time_left)
+                    ),
+                },
+            ),
+            State::Error { error, .. } => {
+                let event = match error.downcast_ref::<netaccess::Error>() {
+                    Some(netaccess::Error::InvalidCredentials(_)) => NotifyEvent::Credentials,
+                    _ => NotifyEvent::Error,
+                };
+                (
+                    event,
+                    Notification {
+                        summary: "Monitor needs attention",
+                        body: format!("{error:#}"),
+                    },
+                )
+            }
+            _ if connection.is_active()
+                && connection.time_left.num_seconds() <= EXPIRY_WARNING
+                && !self.expiry_warned =>
+            {
+                self.expiry_warned = true;
+                (
+                    NotifyEvent::Expiry,
+                    Notification {
+                        summary: "IP address expires soon",
+                        body: format!(
+                            "{} expires in {}",
+                            status?.ip,
+                            self.duration_style.format(&connection.time_left)
+                        ),
+                    },
+                )
+            }
+            _ => return None,
+        };
+        self.events.contains(&event).then_some(notification)
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn show(notification: Notification) {
+    // The monitor runs on regardless, as the interface owns the terminal
+    let result = notify_rust::Notification::new()
+        .appname("netaccess")
+        .summary(notification.summary)
+        .body(&notification.body)
+        .show_async()
+        .await;
+    if let Err(err) = result {
+        tracing::warn!(error = %err, "Failed to show desktop notification");
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+async fn show(_notification: Notification) {}
+
+/// Forwards the states of the monitor to the returned channel, raising desktop notifications
+/// through `org.freedesktop.Notifications` for the given events along the way.
+pub fn tap(
+    events: Vec<NotifyEvent>,
+    duration_style: DurationStyle,
+    status_receiver: watch::Receiver<Option<SystemStatus>>,
+    state_receiver: mpsc::Receiver<State>,
+) -> mpsc::Receiver<State> {
+    let mut notifier = Notifier {
+        events,
+        duration_style,
+        expiry_warned: false,
+    };
+    monitor_tap::tap(status_receiver, state_receiver, move |state, status| {
+        if let Some(notification) = notifier.notification(state, status) {
+            // Notifications are sent on their own task so that D-Bus never holds up the UI
+            tokio::spawn(show(notification));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor_tap::test_util::*;
+    use tokio::sync::oneshot;
+
+    fn notifier(events: Vec<NotifyEvent>) -> Notifier {
+        Notifier {
+            events,
+            duration_style: DurationStyle::Compact,
+            expiry_warned: false,
+        }
+    }
+
+    fn error(error: anyhow::Error) -> State {
+        State::Error {
+            error,
+            retry_sender: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn approval() {
+        let mut notifier = notifier(vec![NotifyEvent::Approval]);
+        let status = status(active(60));
+
+        assert!(notifier
+            .notification(&State::Approving(IP.into()), Some(&status))
+            .is_none());
+        let approved = State::Approved {
+            ip: IP.into(),
+            connection: active(60),
+        };
+        let notification = notifier.notification(&approved, Some(&status)).unwrap();
+        assert_eq!(notification.summary, "IP address approved");
+        assert_eq!(notification.body, "10.21.0.17 is active for 1h");
+    }
+
+    #[test]
+    fn credentials() {
+        let state = error(netaccess::Error::InvalidCredentials(vec![]).into());
+        assert!(notifier(vec![NotifyEvent::Error])
+            .notification(&state, None)
+            .is_none());
+        let notification = notifier(vec![NotifyEvent::Credentials])
+            .notification(&state, None)
+            .unwrap();
+        assert_eq!(notification.summary, "Monitor needs attention");
+    }
+
+    #[test]
+    fn expiry_once() {
+        let mut notifier = notifier(vec![NotifyEvent::Expiry]);
+
+        let notification = notifier
+            .notification(&State::CheckingStatus, Some(&status(active(10))))
+            .unwrap();
+        assert_eq!(notification.body, "10.21.0.17 expires in 10m");
+        assert!(notifier
+            .notification(&State::CheckingStatus, Some(&status(active(5))))
+            .is_none());
+
+        // Warned again once renewed
+        assert!(notifier
+            .notification(&State::CheckingStatus, Some(&status(active(60))))
+            .is_none());
+        assert!(notifier
+            .notification(&State::CheckingStatus, Some(&status(active(10))))
+            .is_some());
+    }
+}
@@ -1,39 +1,36 @@
 use anyhow::{bail, Context};
 use directories::BaseDirs;
-use std::{
-    env,
-    fs::{self},
-    path::PathBuf,
-};
-use uuid::Uuid;
+use std::{ffi::OsStr, fs, io, path::PathBuf};
 
-const CNF_BYTES: &[u8] = include_bytes!("../openssl.cnf");
-
-pub struct OpenSSLConf {
-    path: PathBuf,
-}
-
-impl OpenSSLConf {
-    pub fn new() -> anyhow::Result<Self> {
-        let Some(cache_dir) = BaseDirs::new().map(|dirs| dirs.cache_dir().to_path_buf()) else {
-            bail!("Failed to get cache dir");
-        };
-        let cnf_dir = cache_dir.join("netaccess");
-        fs::create_dir_all(&cnf_dir).context("Failed to create openssl config directory")?;
-        let path = cnf_dir.join(format!("openssl-{}.cnf", Uuid::new_v4()));
-        fs::write(&path, CNF_BYTES).context("Failed to write openssl config contents to file")?;
-        env::set_var("OPENSSL_CONF", path.display().to_string());
-        Ok(Self { path })
-    }
-}
-
-impl Drop for OpenSSLConf {
-    fn drop(&mut self) {
-        if fs::remove_file(&self.path).is_err() {
-            eprintln!(
-                "Failed to remove openssl conf file {}, you may remove it manually",
-                self.path.display()
-            );
+/// Removes the `openssl-*.cnf` files that earlier versions wrote to the cache directory to lower
+/// the OpenSSL security level, which were left behind whenever the process got killed. Returns
+/// the paths of the files removed.
+pub fn remove_leaked() -> anyhow::Result<Vec<PathBuf>> {
+    let Some(cache_dir) = BaseDirs::new().map(|dirs| dirs.cache_dir().to_path_buf()) else {
+        bail!("Failed to get cache dir");
+    };
+    let cnf_dir = cache_dir.join("netaccess");
+    let entries = match fs::read_dir(&cnf_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err).context("Failed to read openssl config directory"),
+    };
+    let mut removed = vec![];
+    for entry in entries {
+        let path = entry
+            .context("Failed to read openssl config directory")?
+            .path();
+        let is_cnf = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(|name| name.starts_with("openssl-") && name.ends_with(".cnf"));
+        if is_cnf {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            removed.push(path);
         }
     }
+    // The directory only ever held config files, so it is left alone unless empty
+    let _ = fs::remove_dir(&cnf_dir);
+    Ok(removed)
 }
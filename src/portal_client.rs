@@ -0,0 +1,225 @@
+use crate::{
+    account_manager::{AccountManager, Error, Report, Status},
+    user::User,
+};
+use std::future::Future;
+
+/// Operations of the netaccess portal, implemented by [`AccountManager`] and by in-memory fakes
+/// in tests so that consumers such as [`Monitor`](crate::Monitor) can run without a network.
+pub trait PortalClient: Send + Sync + 'static {
+    /// Logs in with the credentials of the user, failing with [`Error::InvalidCredentials`] if
+    /// they are rejected.
+    fn login(&self, user: &User) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Queries the status of the system IP address and all other connections of the user.
+    fn status(&self, user: &User) -> impl Future<Output = Result<Status, Error>> + Send;
+
+    /// Approves the system IP address for a duration offered by the portal, matched by label or
+    /// form value, or the default duration if none is given. Approval is skipped if the IP
+    /// address is already active, unless forced.
+    fn approve(
+        &self,
+        user: &User,
+        duration: Option<&str>,
+        force: bool,
+    ) -> impl Future<Output = Result<Report, Error>> + Send;
+
+    /// Revokes the authorization of an IP address, or the system IP address if none is given.
+    fn revoke(
+        &self,
+        user: &User,
+        ip: Option<String>,
+    ) -> impl Future<Output = Result<Report, Error>> + Send;
+}
+
+impl PortalClient for AccountManager {
+    fn login(&self, user: &User) -> impl Future<Output = Result<(), Error>> + Send {
+        self.check_user_password(user)
+    }
+
+    fn status(&self, user: &User) -> impl Future<Output = Result<Status, Error>> + Send {
+        AccountManager::status(self, user)
+    }
+
+    fn approve(
+        &self,
+        user: &User,
+        duration: Option<&str>,
+        force: bool,
+    ) -> impl Future<Output = Result<Report, Error>> + Send {
+        AccountManager::approve(self, user, duration, force)
+    }
+
+    fn revoke(
+        &self,
+        user: &User,
+        ip: Option<String>,
+    ) -> impl Future<Output = Result<Report, Error>> + Send {
+        AccountManager::revoke(self, user, ip)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use super::*;
+    use crate::account_manager::{Connection, DurationOption, Outcome, SystemStatus};
+    use std::{
+        collections::HashMap,
+        net::{IpAddr, Ipv4Addr},
+        sync::{Mutex, MutexGuard},
+        time::Duration,
+    };
+    use tokio::time::Instant;
+
+    pub(crate) const FAKE_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 21, 0, 17));
+
+    /// Durations of the portal approve form along with their labels.
+    const DURATIONS: [(&str, Duration); 3] = [
+        ("60 minutes", Duration::from_secs(60 * 60)),
+        ("1 day", Duration::from_secs(24 * 60 * 60)),
+        ("1 month", Duration::from_secs(30 * 24 * 60 * 60)),
+    ];
+
+    #[derive(Debug, Default)]
+    pub(crate) struct FakeState {
+        /// Instant until which the system IP address is approved, measured with tokio time so
+        /// that it can be simulated.
+        pub active_until: Option<Instant>,
+        /// Number of upcoming calls that fail before the portal recovers.
+        pub failures: usize,
+        pub status_calls: usize,
+        /// Labels of the durations approved for.
+        pub approvals: Vec<String>,
+        pub revocations: usize,
+    }
+
+    /// A portal holding the system IP address alone, which expires according to tokio time.
+    #[derive(Debug, Default)]
+    pub(crate) struct FakePortal {
+        state: Mutex<FakeState>,
+    }
+
+    impl FakePortal {
+        pub(crate) fn state(&self) -> MutexGuard<'_, FakeState> {
+            self.state.lock().expect("Fake portal state poisoned")
+        }
+
+        fn fail(&self) -> Result<(), Error> {
+            let mut state = self.state();
+            if state.failures == 0 {
+                return Ok(());
+            }
+            state.failures -= 1;
+            Err(Error::UnexpectedResponse(String::from("Simulated failure")))
+        }
+
+        fn connection(&self) -> Option<Connection> {
+            self.state().active_until.map(|active_until| {
+                let time_left = active_until.saturating_duration_since(Instant::now());
+                Connection {
+                    time_left: chrono::Duration::from_std(time_left)
+                        .expect("Time left is in range"),
+                    valid_till: None,
+                    is_active: true,
+                }
+            })
+        }
+
+        fn current_status(&self) -> Status {
+            let connection = self.connection();
+            Status {
+                system_status: SystemStatus {
+                    ip: FAKE_IP,
+                    connection: connection.unwrap_or_default(),
+                    clock_skew: chrono::Duration::zero(),
+                },
+                system_registered: connection.is_some(),
+                connections: HashMap::new(),
+                alerts: vec![],
+                durations: DURATIONS
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (label, _))| DurationOption {
+                        value: (index + 1).to_string(),
+                        label: String::from(*label),
+                        is_default: index == 0,
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl PortalClient for FakePortal {
+        async fn login(&self, _user: &User) -> Result<(), Error> {
+            self.fail()
+        }
+
+        async fn status(&self, _user: &User) -> Result<Status, Error> {
+            self.state().status_calls += 1;
+            self.fail()?;
+            Ok(self.current_status())
+        }
+
+        async fn approve(
+            &self,
+            _user: &User,
+            duration: Option<&str>,
+            force: bool,
+        ) -> Result<Report, Error> {
+            self.fail()?;
+            let ip = FAKE_IP;
+            let status = self.current_status();
+            let label = status.duration(duration)?.label.clone();
+            let connection = status.system_status.connection;
+            if !force && connection.is_active() {
+                return Ok(Report {
+                    ip,
+                    outcome: Outcome::AlreadyActive {
+                        time_left: connection.time_left,
+                    },
+                    alerts: vec![],
+                });
+            }
+            {
+                let mut state = self.state();
+                let (_, duration) = DURATIONS
+                    .iter()
+                    .find(|(name, _)| *name == label)
+                    .expect("Label of a fake duration");
+                state.active_until = Some(Instant::now() + *duration);
+                state.approvals.push(label);
+            }
+            Ok(Report {
+                ip,
+                outcome: Outcome::Approved {
+                    connection: self.connection().expect("Approved connection"),
+                },
+                alerts: vec![],
+            })
+        }
+
+        async fn revoke(&self, _user: &User, ip: Option<String>) -> Result<Report, Error> {
+            self.fail()?;
+            let ip = match ip {
+                Some(ip) => ip.parse().map_err(|_| Error::MalformedIp(ip))?,
+                None => FAKE_IP,
+            };
+            let outcome = match self.connection() {
+                _ if ip != FAKE_IP => Outcome::NotRegistered,
+                None => Outcome::NotRegistered,
+                Some(connection) if !connection.is_active() => Outcome::AlreadyInactive,
+                Some(_) => {
+                    let mut state = self.state();
+                    state.active_until = None;
+                    state.revocations += 1;
+                    Outcome::Revoked
+                }
+            };
+            Ok(Report {
+                ip,
+                outcome,
+                alerts: vec![],
+            })
+        }
+    }
+}
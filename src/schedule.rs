@@ -0,0 +1,364 @@
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Weekday};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+/// Schedules repeat weekly, so a phase change is always found within this many days.
+const LOOKAHEAD_DAYS: u64 = 8;
+
+/// What the monitor does with the system IP address during a schedule window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Keep the IP address approved, approving it whenever it becomes inactive.
+    Keep,
+    /// Leave the IP address alone, letting any approval lapse.
+    Lapse,
+    /// Actively revoke the IP address if it is active.
+    Revoke,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Keep => "keep",
+            Self::Lapse => "lapse",
+            Self::Revoke => "revoke",
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+/// Errors encountered while parsing a schedule window.
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+    /// The window is missing its days.
+    #[error("Schedule window {0:?} has no days")]
+    MissingDays(String),
+    /// A day could not be parsed.
+    #[error("Unknown day {0:?}, expected a weekday such as mon or a range such as mon-fri")]
+    UnknownDay(String),
+    /// The time range could not be parsed.
+    #[error("Malformed time range {0:?}, expected HH:MM-HH:MM")]
+    MalformedTimeRange(String),
+    /// The window has tokens after its time range.
+    #[error("Unexpected {0:?} after time range")]
+    TrailingInput(String),
+}
+
+/// A weekly recurring window during which the monitor performs an [`Action`], written as
+/// `[keep|lapse|revoke] <days> [HH:MM-HH:MM]`. Days are a comma separated list of weekdays or
+/// weekday ranges such as `mon-fri,sun`, or `*` for every day. The action defaults to keep and
+/// the time range to the whole day, while ranges ending before they start extend past midnight.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    action: Action,
+    /// Whether the window starts on a weekday, indexed from monday.
+    days: [bool; 7],
+    /// Minutes from midnight the window starts at.
+    start: u32,
+    /// Minutes from midnight the window ends at, extending to the next day if not after start.
+    end: u32,
+    spec: String,
+}
+
+impl Window {
+    /// The action performed during the window.
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    fn starts_on(&self, weekday: Weekday) -> bool {
+        self.days[weekday.num_days_from_monday() as usize]
+    }
+
+    fn contains<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> bool {
+        let minute = time.hour() * 60 + time.minute();
+        let weekday = time.weekday();
+        if self.start < self.end {
+            self.starts_on(weekday) && (self.start..self.end).contains(&minute)
+        } else {
+            (self.starts_on(weekday) && minute >= self.start)
+                || (self.starts_on(weekday.pred()) && minute < self.end)
+        }
+    }
+
+    /// Local times at which the window starts or ends on a date.
+    fn boundaries(&self, date: NaiveDate) -> impl Iterator<Item = DateTime<Local>> {
+        let at = move |minute: u32| {
+            let date = date.checked_add_days(Days::new((minute / MINUTES_PER_DAY).into()))?;
+            let time =
+                NaiveTime::from_num_seconds_from_midnight_opt(minute % MINUTES_PER_DAY * 60, 0)?;
+            Local.from_local_datetime(&date.and_time(time)).earliest()
+        };
+        let end = if self.end > self.start {
+            self.end
+        } else {
+            self.end + MINUTES_PER_DAY
+        };
+        [at(self.start), at(end)].into_iter().flatten()
+    }
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, ScheduleError> {
+    day.parse()
+        .map_err(|_| ScheduleError::UnknownDay(day.to_owned()))
+}
+
+fn parse_days(spec: &str) -> Result<[bool; 7], ScheduleError> {
+    let mut days = [false; 7];
+    if spec == "*" {
+        return Ok([true; 7]);
+    }
+    for part in spec.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+            None => {
+                let day = parse_weekday(part)?;
+                (day, day)
+            }
+        };
+        let mut day = first;
+        loop {
+            days[day.num_days_from_monday() as usize] = true;
+            if day == last {
+                break;
+            }
+            day = day.succ();
+        }
+    }
+    Ok(days)
+}
+
+fn parse_minute(time: &str) -> Option<u32> {
+    if time == "24:00" {
+        return Some(MINUTES_PER_DAY);
+    }
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .ok()
+        .map(|time| time.hour() * 60 + time.minute())
+}
+
+fn parse_time_range(spec: &str) -> Result<(u32, u32), ScheduleError> {
+    let malformed = || ScheduleError::MalformedTimeRange(spec.to_owned());
+    let (start, end) = spec.split_once('-').ok_or_else(malformed)?;
+    match (parse_minute(start), parse_minute(end)) {
+        (Some(start), Some(end)) if start < MINUTES_PER_DAY => Ok((start, end % MINUTES_PER_DAY)),
+        _ => Err(malformed()),
+    }
+}
+
+impl FromStr for Window {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = s.to_ascii_lowercase();
+        let mut tokens = spec.split_whitespace().peekable();
+        let action = match tokens.peek() {
+            Some(&"keep") => Some(Action::Keep),
+            Some(&"lapse") => Some(Action::Lapse),
+            Some(&"revoke") => Some(Action::Revoke),
+            _ => None,
+        };
+        if action.is_some() {
+            tokens.next();
+        }
+        let days = parse_days(
+            tokens
+                .next()
+                .ok_or_else(|| ScheduleError::MissingDays(s.to_owned()))?,
+        )?;
+        let (start, end) = tokens
+            .next()
+            .map(parse_time_range)
+            .transpose()?
+            .unwrap_or((0, 0));
+        if let Some(token) = tokens.next() {
+            return Err(ScheduleError::TrailingInput(token.to_owned()));
+        }
+        Ok(Self {
+            action: action.unwrap_or(Action::Keep),
+            days,
+            start,
+            end,
+            spec: s.trim().to_owned(),
+        })
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.spec.fmt(f)
+    }
+}
+
+/// Phase of a schedule at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phase {
+    /// The action to perform.
+    pub action: Action,
+    /// When the action changes next, if ever.
+    pub until: Option<DateTime<Local>>,
+}
+
+/// A weekly schedule of when the monitor keeps the system IP address approved, lets it lapse or
+/// revokes it. The first window containing a point in time decides the action. Outside of all
+/// windows the IP address lapses if any window keeps it approved, otherwise it is kept approved,
+/// so an empty schedule keeps it approved around the clock.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    /// Creates a schedule from windows in order of precedence.
+    pub fn new(windows: Vec<Window>) -> Self {
+        Self { windows }
+    }
+
+    /// Whether the schedule has no windows and keeps the IP address approved around the clock.
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    fn action(&self, time: &DateTime<Local>) -> Action {
+        self.windows
+            .iter()
+            .find(|window| window.contains(time))
+            .map(Window::action)
+            .unwrap_or_else(|| {
+                if self
+                    .windows
+                    .iter()
+                    .any(|window| window.action == Action::Keep)
+                {
+                    Action::Lapse
+                } else {
+                    Action::Keep
+                }
+            })
+    }
+
+    /// The phase of the schedule at a point in time.
+    pub fn phase(&self, time: DateTime<Local>) -> Phase {
+        let action = self.action(&time);
+        let mut boundaries = (0..LOOKAHEAD_DAYS)
+            .filter_map(|days| time.date_naive().checked_add_days(Days::new(days)))
+            // Windows extending past midnight started the day before
+            .chain(time.date_naive().pred_opt())
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .flat_map(move |window| window.boundaries(date))
+            })
+            .filter(|boundary| *boundary > time)
+            .collect::<Vec<_>>();
+        boundaries.sort();
+        Phase {
+            action,
+            until: boundaries
+                .into_iter()
+                .find(|boundary| self.action(boundary) != action),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        // 2023-07-24 is a monday
+        Local
+            .with_ymd_and_hms(2023, 7, day, hour, minute, 0)
+            .single()
+            .expect("Valid local time")
+    }
+
+    fn schedule(windows: &[&str]) -> Schedule {
+        Schedule::new(
+            windows
+                .iter()
+                .map(|window| window.parse().unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parse() {
+        let window: Window = "mon-fri 09:00-18:00".parse().unwrap();
+        assert_eq!(window.action(), Action::Keep);
+        assert_eq!(window.days, [true, true, true, true, true, false, false]);
+        assert_eq!((window.start, window.end), (9 * 60, 18 * 60));
+
+        let window: Window = "Revoke sat,sun".parse().unwrap();
+        assert_eq!(window.action(), Action::Revoke);
+        assert_eq!(window.days, [false, false, false, false, false, true, true]);
+        assert_eq!((window.start, window.end), (0, 0));
+
+        let window: Window = "lapse fri-mon 22:00-24:00".parse().unwrap();
+        assert_eq!(window.days, [true, false, false, false, true, true, true]);
+        assert_eq!((window.start, window.end), (22 * 60, 0));
+
+        assert!("keep".parse::<Window>().is_err());
+        assert!("keep someday".parse::<Window>().is_err());
+        assert!("keep * 9-18".parse::<Window>().is_err());
+        assert!("keep * 09:00-18:00 now".parse::<Window>().is_err());
+    }
+
+    #[test]
+    fn empty_schedule_keeps() {
+        let phase = Schedule::default().phase(time(24, 12, 0));
+        assert_eq!(
+            phase,
+            Phase {
+                action: Action::Keep,
+                until: None,
+            }
+        );
+    }
+
+    #[test]
+    fn working_hours() {
+        let schedule = schedule(&["mon-fri 09:00-18:00"]);
+
+        let phase = schedule.phase(time(24, 12, 0));
+        assert_eq!(phase.action, Action::Keep);
+        assert_eq!(phase.until, Some(time(24, 18, 0)));
+
+        let phase = schedule.phase(time(24, 18, 0));
+        assert_eq!(phase.action, Action::Lapse);
+        assert_eq!(phase.until, Some(time(25, 9, 0)));
+
+        // Friday evening lapses until monday morning
+        let phase = schedule.phase(time(28, 20, 0));
+        assert_eq!(phase.action, Action::Lapse);
+        assert_eq!(phase.until, Some(time(31, 9, 0)));
+    }
+
+    #[test]
+    fn overnight_revoke() {
+        let schedule = schedule(&["revoke * 23:00-06:00"]);
+
+        assert_eq!(schedule.phase(time(24, 12, 0)).action, Action::Keep);
+        let phase = schedule.phase(time(25, 2, 0));
+        assert_eq!(phase.action, Action::Revoke);
+        assert_eq!(phase.until, Some(time(25, 6, 0)));
+        assert_eq!(schedule.phase(time(24, 23, 30)).action, Action::Revoke);
+    }
+
+    #[test]
+    fn first_window_takes_precedence() {
+        let schedule = schedule(&["revoke sat", "keep * 08:00-20:00"]);
+
+        assert_eq!(schedule.phase(time(29, 10, 0)).action, Action::Revoke);
+        assert_eq!(schedule.phase(time(30, 10, 0)).action, Action::Keep);
+        assert_eq!(schedule.phase(time(30, 21, 0)).action, Action::Lapse);
+    }
+}
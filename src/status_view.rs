@@ -0,0 +1,288 @@
+use crate::monitor_ui;
+use chrono::Local;
+use crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    queue,
+    style::{Print, PrintStyledContent, Stylize},
+    terminal::{self, Clear, ClearType},
+};
+use netaccess::{AccountManager, Connection, DurationStyle, Status, SystemStatus, User};
+use std::{
+    io::{self, IsTerminal, Write},
+    net::IpAddr,
+    time::Duration,
+};
+
+/// Columns between tab stops of terminals.
+const TAB_WIDTH: usize = 8;
+
+/// How a line of the status differs from the previous refresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Unchanged,
+    /// The connection showed up since the previous refresh.
+    Appeared,
+    /// The connection became active.
+    Activated,
+    /// The connection expired or got revoked.
+    Deactivated,
+    /// The connection is no longer registered.
+    Removed,
+}
+
+impl Change {
+    fn between(previous: Option<&Connection>, connection: &Connection) -> Self {
+        match previous {
+            None => Self::Appeared,
+            Some(previous) if !previous.is_active() && connection.is_active() => Self::Activated,
+            Some(previous) if previous.is_active() && !connection.is_active() => Self::Deactivated,
+            Some(_) => Self::Unchanged,
+        }
+    }
+
+    fn marker(self) -> &'static str {
+        match self {
+            Self::Unchanged => "",
+            Self::Appeared => " (new)",
+            Self::Activated => " (activated)",
+            Self::Deactivated => " (expired)",
+            Self::Removed => " (removed)",
+        }
+    }
+}
+
+struct Line {
+    text: String,
+    change: Change,
+}
+
+impl Line {
+    fn new(text: String) -> Self {
+        Self {
+            text,
+            change: Change::Unchanged,
+        }
+    }
+
+    /// Text of the line followed by the marker of its change, if any.
+    fn marked(&self) -> String {
+        format!("{}{}", self.text, self.change.marker())
+    }
+}
+
+/// Number of rows the text takes up on a terminal of the given width, as long lines wrap and
+/// tabs advance to the next tab stop.
+fn rows(text: &str, columns: usize) -> usize {
+    text.split('\n')
+        .map(|line| {
+            let width = line.chars().fold(0, |column, char| match char {
+                '\t' => (column / TAB_WIDTH + 1) * TAB_WIDTH,
+                _ => column + 1,
+            });
+            width.div_ceil(columns.max(1)).max(1)
+        })
+        .sum()
+}
+
+fn time_left(connection: &Connection, duration_style: DurationStyle) -> String {
+    if connection.is_active() {
+        duration_style.format(&connection.time_left)
+    } else {
+        String::from("Inactive or expired")
+    }
+}
+
+/// Lines describing the status, marking what changed since the previous status if any.
+/// Connections are sorted by IP address so that they keep their place across refreshes.
+fn lines(status: &Status, previous: Option<&Status>, duration_style: DurationStyle) -> Vec<Line> {
+    let SystemStatus { ip, connection, .. } = status.system_status;
+    let mut lines = vec![Line {
+        text: format!(
+            "Your IP address is {ip} and {}",
+            if connection.is_active() {
+                format!(
+                    "active for {}",
+                    duration_style.format(&connection.time_left)
+                )
+            } else {
+                String::from("inactive")
+            }
+        ),
+        change: match previous {
+            Some(previous) if previous.system_status.ip == ip => {
+                Change::between(Some(&previous.system_status.connection), &connection)
+            }
+            Some(_) => Change::Appeared,
+            None => Change::Unchanged,
+        },
+    }];
+
+    let connections = status.connections();
+    lines.push(Line::new(format!(
+        "Number of other registered connections: {}",
+        connections.len()
+    )));
+    let removed = previous
+        .map(|previous| {
+            let mut removed = previous
+                .connections()
+                .keys()
+                .filter(|ip| !connections.contains_key(ip))
+                .copied()
+                .collect::<Vec<IpAddr>>();
+            removed.sort();
+            removed
+        })
+        .unwrap_or_default();
+    if !connections.is_empty() || !removed.is_empty() {
+        lines.push(Line::new(String::from("S.No.\tIP\t\tTime left")));
+    }
+    let mut sorted = connections.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|(ip, _)| **ip);
+    for (index, (ip, connection)) in sorted.into_iter().enumerate() {
+        lines.push(Line {
+            text: format!(
+                "{}\t{ip}\t{}",
+                index + 1,
+                time_left(connection, duration_style)
+            ),
+            change: previous.map_or(Change::Unchanged, |previous| {
+                Change::between(previous.connections().get(ip), connection)
+            }),
+        });
+    }
+    for ip in removed {
+        lines.push(Line {
+            text: format!("-\t{ip}\t-"),
+            change: Change::Removed,
+        });
+    }
+    lines
+}
+
+/// Prints the status once.
+pub fn print(status: &Status, duration_style: DurationStyle) {
+    if let Some(warning) = monitor_ui::clock_skew_warning(&status.system_status, duration_style) {
+        eprintln!("Warning: {warning}");
+    }
+    for line in lines(status, None, duration_style) {
+        println!("{}", line.text);
+    }
+}
+
+/// Writes the lines in place of the given number of terminal rows written before, coloring
+/// changes if styled.
+fn render(
+    stdout: &mut impl Write,
+    lines: &[Line],
+    replaced: usize,
+    styled: bool,
+) -> io::Result<()> {
+    if replaced > 0 {
+        queue!(
+            stdout,
+            MoveUp(replaced.try_into().unwrap_or(u16::MAX)),
+            MoveToColumn(0),
+            Clear(ClearType::FromCursorDown)
+        )?;
+    }
+    for line in lines {
+        let text = line.marked();
+        match line.change {
+            _ if !styled => queue!(stdout, Print(text))?,
+            Change::Unchanged => queue!(stdout, Print(text))?,
+            Change::Appeared | Change::Activated => {
+                queue!(stdout, PrintStyledContent(text.green()))?
+            }
+            Change::Deactivated | Change::Removed => {
+                queue!(stdout, PrintStyledContent(text.red()))?
+            }
+        }
+        queue!(stdout, Print("\n"))?;
+    }
+    stdout.flush()
+}
+
+/// Queries and prints the status every interval until interrupted, redrawing it in place when
+/// printing to a terminal and highlighting what changed since the previous refresh. Failed
+/// refreshes are reported below the last known status, except for rejected credentials.
+pub async fn watch(
+    account_manager: &AccountManager,
+    user: &User,
+    interval: Duration,
+    duration_style: DurationStyle,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let mut stdout = io::stdout();
+    let in_place = stdout.is_terminal();
+    let mut previous: Option<Status> = None;
+    // Lines written by the previous refresh, measured again on redraw in case the terminal was
+    // resized since
+    let mut rendered: Vec<String> = vec![];
+    loop {
+        let mut lines = match account_manager.status(user).await {
+            Ok(status) => {
+                let mut lines = lines(&status, previous.as_ref(), duration_style);
+                if let Some(warning) =
+                    monitor_ui::clock_skew_warning(&status.system_status, duration_style)
+                {
+                    lines.push(Line::new(format!("Warning: {warning}")));
+                }
+                lines.extend(
+                    status
+                        .alerts()
+                        .iter()
+                        .map(|alert| Line::new(format!("Portal says: {alert}"))),
+                );
+                previous = Some(status);
+                lines
+            }
+            Err(err @ netaccess::Error::InvalidCredentials(_)) => return Err(err.into()),
+            Err(err) => {
+                let mut lines = previous
+                    .as_ref()
+                    .map(|previous| lines(previous, None, duration_style))
+                    .unwrap_or_default();
+                lines.push(Line {
+                    text: format!("Refresh failed: {err}"),
+                    change: Change::Unchanged,
+                });
+                lines
+            }
+        };
+        lines.push(Line::new(format!(
+            "Refreshed at {}, every {} seconds",
+            Local::now().format("%H:%M:%S"),
+            interval.as_secs()
+        )));
+        if !quiet {
+            let replaced = if in_place {
+                let columns = terminal::size().map_or(usize::MAX, |(columns, _)| columns.into());
+                rendered.iter().map(|text| rows(text, columns)).sum()
+            } else {
+                println!();
+                0
+            };
+            render(&mut stdout, &lines, replaced, in_place)?;
+            rendered = lines.iter().map(Line::marked).collect();
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapped_rows() {
+        assert_eq!(rows("", 80), 1);
+        assert_eq!(rows(&"x".repeat(80), 80), 1);
+        assert_eq!(rows(&"x".repeat(81), 80), 2);
+        assert_eq!(rows("1\t10.21.0.17\t1 hour", 80), 1);
+        // Tabs advance to the next multiple of eight columns
+        assert_eq!(rows("1\t10.21.0.17\t1 hour", 20), 2);
+        assert_eq!(rows("Refresh failed\nCaused by", 80), 2);
+        assert_eq!(rows("x", 0), 1);
+    }
+}
@@ -0,0 +1,10 @@
+//! Helpers shared by the unit tests.
+
+/// Parses a portal page saved in `tests/fixtures`.
+macro_rules! fixture {
+    ( $name:literal ) => {
+        scraper::Html::parse_document(include_str!(concat!("../tests/fixtures/", $name, ".html")))
+    };
+}
+
+pub(crate) use fixture;
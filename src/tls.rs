@@ -0,0 +1,617 @@
+use crate::{http_config::HttpConfig, native_backend};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::ClientBuilder;
+use ring::digest::{digest, SHA256};
+use rustls::{
+    cipher_suite::{
+        TLS13_AES_128_GCM_SHA256, TLS13_AES_256_GCM_SHA384, TLS13_CHACHA20_POLY1305_SHA256,
+        TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+        TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256, TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+        TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384, TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+    },
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    version::{TLS12, TLS13},
+    AlertDescription, Certificate, CertificateError, ClientConfig, RootCertStore, ServerName,
+    SupportedCipherSuite,
+};
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tokio::net::{self, TcpSocket, TcpStream};
+use tokio_rustls::TlsConnector;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Cipher suites offered to the portal when certificates are pinned, which needs rustls. rustls
+/// supports no finite field Diffie-Hellman, so pinned connections rely on the portal offering
+/// ECDHE as well. They fail as incompatible otherwise, and `tls-check` fails the same way.
+const CIPHER_SUITES: [SupportedCipherSuite; 9] = [
+    TLS13_AES_256_GCM_SHA384,
+    TLS13_AES_128_GCM_SHA256,
+    TLS13_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+    TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+];
+
+/// Why a TLS handshake with the portal failed, for failures that another attempt does not fix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HandshakeFailure {
+    /// The certificate of the portal failed verification against the trusted roots or pins.
+    CertificateRejected,
+    /// The portal and the TLS library share no protocol version, cipher suite or key exchange.
+    Incompatible,
+}
+
+/// Classifies the failure behind an error of either TLS backend, which reaches reqwest and
+/// tokio wrapped in I/O errors that do not expose it as a source. Other failures, such as those
+/// of the network, are not classified.
+pub(crate) fn handshake_failure(err: &(dyn StdError + 'static)) -> Option<HandshakeFailure> {
+    std::iter::successors(Some(err), |&err| err.source()).find_map(|mut err| loop {
+        if let Some(rustls_err) = err.downcast_ref::<rustls::Error>() {
+            return rustls_failure(rustls_err);
+        }
+        if let Some(failure) = native_backend::handshake_failure(err) {
+            return Some(failure);
+        }
+        // The source of an I/O error is the source of the error it wraps, skipping that error
+        err = err.downcast_ref::<io::Error>()?.get_ref()?;
+    })
+}
+
+fn rustls_failure(err: &rustls::Error) -> Option<HandshakeFailure> {
+    match err {
+        rustls::Error::InvalidCertificate(_) => Some(HandshakeFailure::CertificateRejected),
+        rustls::Error::PeerIncompatible(_)
+        | rustls::Error::AlertReceived(
+            AlertDescription::HandshakeFailure
+            | AlertDescription::InsufficientSecurity
+            | AlertDescription::ProtocolVersion,
+        ) => Some(HandshakeFailure::Incompatible),
+        _ => None,
+    }
+}
+
+/// Prefix of public key pins, as accepted by `curl --pinnedpubkey`.
+const PUBLIC_KEY_PIN_PREFIX: &str = "sha256//";
+
+/// Errors encountered while configuring TLS for the portal or checking its certificate.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum TlsError {
+    /// The root certificates of the system could not be read.
+    #[error("Failed to load system root certificates: {0}")]
+    SystemRoots(#[source] io::Error),
+    /// A CA bundle could not be read.
+    #[error("Failed to read CA bundle {}: {source}", .path.display())]
+    CaBundle {
+        /// Path of the bundle.
+        path: PathBuf,
+        /// Why it could not be read.
+        source: io::Error,
+    },
+    /// A CA bundle holds no certificate that could be parsed.
+    #[error("CA bundle {} holds no usable PEM certificate", .0.display())]
+    EmptyCaBundle(PathBuf),
+    /// Neither the system trust store nor the CA bundles hold a certificate that could be parsed.
+    #[error("No usable root certificates found in the system trust store")]
+    NoRoots,
+    /// rustls rejected the protocol configuration.
+    #[error("Invalid TLS configuration: {0}")]
+    Config(#[from] rustls::Error),
+    /// The TLS library of the platform could not be configured, such as OpenSSL failing to
+    /// load the configuration lowering its security level.
+    #[error("Failed to configure the TLS library of the platform: {0}")]
+    Platform(String),
+    /// The portal could not be reached or the handshake with it failed.
+    #[error("TLS handshake with {host} failed: {source}")]
+    Handshake {
+        /// Host name of the portal.
+        host: String,
+        /// Why the handshake failed.
+        source: io::Error,
+    },
+}
+
+impl TlsError {
+    /// Whether the handshake failed because the portal offers no protocol version, cipher suite
+    /// or key exchange that is supported.
+    pub fn is_incompatible(&self) -> bool {
+        matches!(self, Self::Handshake { source, .. } if handshake_failure(source) == Some(HandshakeFailure::Incompatible))
+    }
+}
+
+/// Errors encountered while parsing a [`Pin`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "Pin {0} is neither a certificate fingerprint such as AB:CD:… nor a public key pin such as \
+     sha256//base64, both of SHA-256"
+)]
+pub struct PinError(String);
+
+/// SHA-256 fingerprint that the certificate presented by the portal must match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pin {
+    /// Fingerprint of a whole DER encoded certificate, written as colon separated hex digits as
+    /// printed by `openssl x509 -fingerprint -sha256`.
+    Certificate([u8; 32]),
+    /// Fingerprint of the DER encoded subject public key info of a certificate, written as
+    /// `sha256//` followed by base64 as accepted by `curl --pinnedpubkey`. Unlike certificate
+    /// pins, these survive renewals that keep the key.
+    PublicKey([u8; 32]),
+}
+
+impl Pin {
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        digest(&SHA256, data)
+            .as_ref()
+            .try_into()
+            .expect("SHA-256 digests are 32 bytes long")
+    }
+
+    /// Whether the DER encoded certificate matches the pin.
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        match self {
+            Self::Certificate(fingerprint) => Self::sha256(certificate) == *fingerprint,
+            Self::PublicKey(fingerprint) => X509Certificate::from_der(certificate)
+                .is_ok_and(|(_, parsed)| Self::sha256(parsed.public_key().raw) == *fingerprint),
+        }
+    }
+}
+
+impl FromStr for Pin {
+    type Err = PinError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || PinError(s.to_owned());
+        if let Some(encoded) = s.strip_prefix(PUBLIC_KEY_PIN_PREFIX) {
+            let decoded = BASE64.decode(encoded).map_err(|_| error())?;
+            return decoded.try_into().map(Self::PublicKey).map_err(|_| error());
+        }
+        let digits = s.replace(':', "");
+        if digits.len() != 64 || !digits.is_ascii() {
+            return Err(error());
+        }
+        let mut fingerprint = [0; 32];
+        for (byte, pair) in fingerprint.iter_mut().zip(digits.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| error())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| error())?;
+        }
+        Ok(Self::Certificate(fingerprint))
+    }
+}
+
+impl Display for Pin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Certificate(fingerprint) => {
+                let digits = fingerprint
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect::<Vec<_>>();
+                digits.join(":").fmt(f)
+            }
+            Self::PublicKey(fingerprint) => {
+                write!(f, "{PUBLIC_KEY_PIN_PREFIX}{}", BASE64.encode(fingerprint))
+            }
+        }
+    }
+}
+
+/// The certificate chain of the portal matches none of the pins.
+#[derive(thiserror::Error, Debug)]
+#[error("Portal certificate chain matches none of the pinned fingerprints")]
+struct PinMismatch;
+
+/// How the certificate of the portal is verified, in addition to the root certificates of the
+/// system.
+///
+/// The portal is reached with the TLS library of the platform, OpenSSL with its security level
+/// lowered to 1 outside of Windows and macOS, as the portal negotiates finite field
+/// Diffie-Hellman with small parameters. Pinning needs to verify the certificate before any
+/// credentials are sent, which only rustls allows, so pinned connections need the portal to
+/// offer ECDHE.
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM files of extra root certificates to trust, such as the CA of an intercepting proxy.
+    pub ca_bundles: Vec<PathBuf>,
+    /// Fingerprints of which the portal certificate must match at least one, if any.
+    /// Connections fail before any credentials are sent otherwise.
+    pub pins: Vec<Pin>,
+}
+
+impl TlsConfig {
+    fn roots(&self) -> Result<RootCertStore, TlsError> {
+        let mut roots = RootCertStore::empty();
+        let certificates =
+            rustls_native_certs::load_native_certs().map_err(TlsError::SystemRoots)?;
+        roots.add_parsable_certificates(&certificates);
+        for path in &self.ca_bundles {
+            if roots.add_parsable_certificates(&read_ca_bundle(path)?).0 == 0 {
+                return Err(TlsError::EmptyCaBundle(path.clone()));
+            }
+        }
+        if roots.is_empty() {
+            return Err(TlsError::NoRoots);
+        }
+        Ok(roots)
+    }
+
+    fn build(&self, verifier: Arc<dyn ServerCertVerifier>) -> Result<ClientConfig, TlsError> {
+        Ok(ClientConfig::builder()
+            .with_cipher_suites(&CIPHER_SUITES)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&TLS13, &TLS12])?
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth())
+    }
+
+    /// Configures TLS for the portal client, allowing TLS 1.2 and 1.3 only. rustls is used if
+    /// there are pins, the TLS library of the platform otherwise.
+    pub(crate) fn configure(&self, builder: ClientBuilder) -> Result<ClientBuilder, TlsError> {
+        if self.pins.is_empty() {
+            return native_backend::configure(self, builder);
+        }
+        Ok(
+            builder.use_preconfigured_tls(self.build(Arc::new(PinningVerifier {
+                roots: Box::new(WebPkiVerifier::new(self.roots()?, None)),
+                pins: self.pins.clone(),
+            }))?),
+        )
+    }
+}
+
+/// Reads the DER encoded certificates of a CA bundle.
+pub(crate) fn read_ca_bundle(path: &PathBuf) -> Result<Vec<Vec<u8>>, TlsError> {
+    File::open(path)
+        .and_then(|file| rustls_pemfile::certs(&mut BufReader::new(file)))
+        .map_err(|source| TlsError::CaBundle {
+            path: path.clone(),
+            source,
+        })
+}
+
+/// Verifies certificates against the trusted roots, then the certificate of the portal itself
+/// against the pins if there are any. Intermediates are never matched against the pins, as the
+/// server chooses which ones to send and could add a pinned one to a misissued certificate.
+struct PinningVerifier {
+    roots: Box<dyn ServerCertVerifier>,
+    pins: Vec<Pin>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.roots.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        if self.pins.is_empty() || self.pins.iter().any(|pin| pin.matches(&end_entity.0)) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                Arc::new(PinMismatch),
+            )))
+        }
+    }
+}
+
+/// Lets every handshake go through while keeping the outcome of the actual verification, so
+/// that the chain of a portal that fails it can still be inspected. No data is ever sent over
+/// connections verified this way.
+struct RecordingVerifier {
+    verifier: PinningVerifier,
+    outcome: Mutex<Option<Result<(), rustls::Error>>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let outcome = self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        );
+        *self.outcome.lock().expect("Verification lock poisoned") = Some(outcome.map(|_| ()));
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// A certificate presented by the portal.
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    /// Distinguished name of the subject.
+    pub subject: String,
+    /// Distinguished name of the issuer.
+    pub issuer: String,
+    /// Time after which the certificate is no longer valid.
+    pub not_after: DateTime<Utc>,
+    /// Pin of the whole certificate.
+    pub fingerprint: Pin,
+    /// Pin of the public key of the certificate.
+    pub public_key: Pin,
+}
+
+impl CertificateInfo {
+    pub(crate) fn parse(certificate: &[u8]) -> Option<Self> {
+        let (_, parsed) = X509Certificate::from_der(certificate).ok()?;
+        Some(Self {
+            subject: parsed.subject().to_string(),
+            issuer: parsed.issuer().to_string(),
+            not_after: Utc
+                .timestamp_opt(parsed.validity().not_after.timestamp(), 0)
+                .single()?,
+            fingerprint: Pin::Certificate(Pin::sha256(certificate)),
+            public_key: Pin::PublicKey(Pin::sha256(parsed.public_key().raw)),
+        })
+    }
+}
+
+/// Outcome of a TLS handshake with the portal.
+#[derive(Debug, Clone)]
+pub struct TlsReport {
+    /// Negotiated protocol version, such as `TLSv1.3`, unless the TLS library of the platform
+    /// does not report it.
+    pub protocol: Option<String>,
+    /// Negotiated cipher suite, such as `TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384`, unless the TLS
+    /// library of the platform does not report it.
+    pub cipher_suite: Option<String>,
+    /// Certificates presented by the portal that could be parsed, starting with its own. The
+    /// TLS libraries of Windows and macOS only report the certificate of the portal.
+    pub chain: Vec<CertificateInfo>,
+    /// Why the chain failed verification against the trusted roots and pins, if it did.
+    pub verification_error: Option<String>,
+}
+
+/// Connects to the host on port 443 directly over the configured family and from the
+/// configured local address, even if a proxy is configured.
+pub(crate) async fn connect(config: &HttpConfig, host: &str) -> io::Result<TcpStream> {
+    let family = config.effective_family();
+    let address = net::lookup_host((host, 443))
+        .await?
+        .find(|address| family.is_none_or(|family| family.matches(&address.ip())))
+        .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+    let socket = if address.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    if let Some(local_address) = config.local_address {
+        socket.bind(SocketAddr::new(local_address, 0))?;
+    }
+    socket.connect(address).await
+}
+
+/// Runs a handshake with the host within the configured timeout.
+pub(crate) async fn handshake<T>(
+    config: &HttpConfig,
+    host: &str,
+    handshake: impl Future<Output = io::Result<T>>,
+) -> Result<T, TlsError> {
+    let handshake_error = |source| TlsError::Handshake {
+        host: host.to_owned(),
+        source,
+    };
+    tokio::time::timeout(config.timeout, handshake)
+        .await
+        .map_err(|_| handshake_error(io::ErrorKind::TimedOut.into()))?
+        .map_err(handshake_error)
+}
+
+/// Performs a TLS handshake with the host on port 443 like the portal client does, without
+/// sending any data, reporting what was negotiated and whether the certificate chain passes
+/// verification. The connection is made as by [`connect`].
+pub(crate) async fn check(config: &HttpConfig, host: &str) -> Result<TlsReport, TlsError> {
+    if config.tls.pins.is_empty() {
+        native_backend::check(config, host).await
+    } else {
+        check_pinned(config, host).await
+    }
+}
+
+async fn check_pinned(config: &HttpConfig, host: &str) -> Result<TlsReport, TlsError> {
+    let verifier = Arc::new(RecordingVerifier {
+        verifier: PinningVerifier {
+            roots: Box::new(WebPkiVerifier::new(config.tls.roots()?, None)),
+            pins: config.tls.pins.clone(),
+        },
+        outcome: Mutex::new(None),
+    });
+    let connector = TlsConnector::from(Arc::new(config.tls.build(Arc::clone(&verifier) as _)?));
+    let stream = handshake(config, host, async {
+        let server_name = ServerName::try_from(host)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        connector
+            .connect(server_name, connect(config, host).await?)
+            .await
+    })
+    .await?;
+
+    let (_, connection) = stream.get_ref();
+    let outcome = verifier
+        .outcome
+        .lock()
+        .expect("Verification lock poisoned")
+        .take();
+    Ok(TlsReport {
+        // Named as by OpenSSL
+        protocol: connection
+            .protocol_version()
+            .map(|version| format!("{version:?}").replace('_', ".")),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite())),
+        chain: connection
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|certificate| CertificateInfo::parse(&certificate.0))
+            .collect(),
+        verification_error: match outcome {
+            Some(Ok(())) => None,
+            Some(Err(err)) => Some(err.to_string()),
+            None => Some(String::from("No certificate was presented")),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "27:16:0E:67:A9:48:A2:7A:41:D8:D7:32:EE:F2:47:B3:C4:CC:23:C9:44:BF:C7:DD:F8:B0:33:02:85:FC:82:EA";
+    const PUBLIC_KEY: &str = "sha256//OGIqSvNUhM4FWucXnpZS4i+msRlRbgnCt7zGRXUYXTk=";
+
+    fn certificate() -> Vec<u8> {
+        let pem = include_bytes!("../tests/fixtures/portal_cert.pem");
+        rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0)
+    }
+
+    #[test]
+    fn pins() {
+        for pin in [FINGERPRINT, PUBLIC_KEY] {
+            assert_eq!(pin.parse::<Pin>().unwrap().to_string(), pin);
+        }
+        assert_eq!(
+            FINGERPRINT.to_lowercase().replace(':', "").parse::<Pin>(),
+            FINGERPRINT.parse()
+        );
+        for malformed in [
+            "27:16:0E",
+            "sha256//not base64",
+            "sha256//AAAA",
+            "ZZ".repeat(32).as_str(),
+        ] {
+            assert!(malformed.parse::<Pin>().is_err(), "{malformed}");
+        }
+
+        let certificate = certificate();
+        assert!(FINGERPRINT.parse::<Pin>().unwrap().matches(&certificate));
+        assert!(PUBLIC_KEY.parse::<Pin>().unwrap().matches(&certificate));
+        assert!(!Pin::Certificate([0; 32]).matches(&certificate));
+        assert!(!Pin::PublicKey([0; 32]).matches(&certificate));
+    }
+
+    struct AcceptAll;
+
+    impl ServerCertVerifier for AcceptAll {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    #[test]
+    fn pins_end_entity_only() {
+        let verifier = PinningVerifier {
+            roots: Box::new(AcceptAll),
+            pins: vec![FINGERPRINT.parse().unwrap()],
+        };
+        let verify = |end_entity: &Certificate, intermediates: &[Certificate]| {
+            verifier.verify_server_cert(
+                end_entity,
+                intermediates,
+                &ServerName::try_from("netaccess.iitm.ac.in").unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+        };
+        let pinned = Certificate(certificate());
+        let misissued = Certificate(b"misissued".to_vec());
+
+        assert!(verify(&pinned, &[]).is_ok());
+        assert!(matches!(
+            verify(&misissued, std::slice::from_ref(&pinned)),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn nested_rustls_error() {
+        let err = io::Error::other(io::Error::new(
+            io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(CertificateError::Expired),
+        ));
+        assert_eq!(
+            handshake_failure(&err),
+            Some(HandshakeFailure::CertificateRejected)
+        );
+        assert!(handshake_failure(&io::Error::from(io::ErrorKind::TimedOut)).is_none());
+    }
+
+    #[test]
+    fn incompatible() {
+        let handshake = |err: rustls::Error| TlsError::Handshake {
+            host: String::from("netaccess.iitm.ac.in"),
+            source: io::Error::new(io::ErrorKind::InvalidData, err),
+        };
+        assert!(
+            handshake(rustls::PeerIncompatible::NoCipherSuitesInCommon.into()).is_incompatible()
+        );
+        assert!(handshake(rustls::Error::AlertReceived(
+            AlertDescription::HandshakeFailure
+        ))
+        .is_incompatible());
+        assert!(
+            !handshake(rustls::Error::InvalidCertificate(CertificateError::Expired))
+                .is_incompatible()
+        );
+        assert!(!TlsError::Handshake {
+            host: String::from("netaccess.iitm.ac.in"),
+            source: io::ErrorKind::TimedOut.into(),
+        }
+        .is_incompatible());
+    }
+
+    #[test]
+    fn certificate_info() {
+        let info = CertificateInfo::parse(&certificate()).unwrap();
+        assert_eq!(info.subject, "CN=netaccess.iitm.ac.in, O=Test");
+        assert_eq!(info.fingerprint.to_string(), FINGERPRINT);
+        assert_eq!(info.public_key.to_string(), PUBLIC_KEY);
+        assert_eq!(info.not_after.to_rfc3339(), "2126-09-24T13:05:38+00:00");
+    }
+}
@@ -1,25 +1,38 @@
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, Clone)]
+/// LDAP credentials of a user account.
+#[derive(Clone)]
 pub struct User {
     name: String,
     password: String,
 }
 
 impl User {
+    /// Creates credentials from a user name such as `XX19X001` and its password.
     pub fn new(name: String, password: String) -> Self {
         Self { name, password }
     }
 
+    /// The LDAP user name.
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// The LDAP password.
     pub fn password(&self) -> &str {
         &self.password
     }
 }
 
+// The password is left out, so that credentials never end up in logs or error messages
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Display for User {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("User {}", self.name))